@@ -0,0 +1,70 @@
+//! Up to four videos side by side, each with its own player, all drawn by
+//! one window:
+//!
+//! ```sh
+//! cargo run --example grid -- a.mp4 b.mp4 c.mp4 d.mp4
+//! ```
+
+use std::rc::Rc;
+
+use mpv_player::mpv::command::Command;
+use mpv_player::MpvWindow;
+use slint::{Model, VecModel};
+
+slint::slint! {
+    import { MpvVideo } from "ui/mpv-widget.slint";
+
+    export component Grid inherits Window {
+        in property <[image]> textures;
+        // the cells are the same size
+        out property <int> cell-width: first.requested-texture-width;
+        out property <int> cell-height: first.requested-texture-height;
+
+        preferred-width: 960px;
+        preferred-height: 540px;
+        title: "Grid";
+
+        VerticalLayout {
+            HorizontalLayout {
+                first := MpvVideo { texture: textures[0]; }
+                MpvVideo { texture: textures[1]; }
+            }
+            HorizontalLayout {
+                MpvVideo { texture: textures[2]; }
+                MpvVideo { texture: textures[3]; }
+            }
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let grid = Grid::new().unwrap();
+    let textures = Rc::new(VecModel::from(vec![slint::Image::default(); 4]));
+    grid.set_textures(textures.clone().into());
+
+    let window = MpvWindow::new(&grid).unwrap();
+    let mut players = vec![];
+    for (i, url) in std::env::args_os()
+        .skip(1)
+        .take(textures.row_count())
+        .enumerate()
+    {
+        let textures = textures.clone();
+        let grid_ = grid.as_weak();
+        let player = window
+            .attach(
+                move |image| textures.set_row_data(i, image),
+                move || match grid_.upgrade() {
+                    Some(grid) => (grid.get_cell_width() as u32, grid.get_cell_height() as u32),
+                    None => (0, 0),
+                },
+            )
+            .unwrap();
+        player.mpv().command(&Command::load_file(url)).unwrap();
+        players.push(player);
+    }
+
+    grid.run().unwrap();
+}
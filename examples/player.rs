@@ -0,0 +1,105 @@
+/*
+Copyright (c) 2024 maurges <contact@morj.men>
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! The demo player: one video with controls
+//!
+//! ```sh
+//! cargo run --example player
+//! ```
+
+use mpv_player::mpv;
+
+slint::slint! {
+    import { App, MpvPlayer, MpvTrack, MpvChapter } from "ui/main.slint";
+    export { App, MpvPlayer, MpvTrack, MpvChapter }
+}
+
+fn main() {
+    env_logger::init();
+
+    let app = App::new().unwrap();
+    let app_weak = app.as_weak();
+
+    let app_weak_ = app_weak.clone();
+    let app_weak__ = app_weak.clone();
+    let widget = mpv_player::MpvWidget::attach(
+        &app,
+        move |image| {
+            if let Some(app) = app_weak_.upgrade() {
+                app.set_texture(image);
+            }
+        },
+        move || match app_weak__.upgrade() {
+            Some(app) => (
+                app.get_requested_texture_width() as u32,
+                app.get_requested_texture_height() as u32,
+            ),
+            None => (0, 0),
+        },
+    )
+    .unwrap();
+    let mpv = widget.mpv().clone();
+    // terminal is off, so see mpv output with RUST_LOG=mpv=info and the like
+    mpv.request_log_messages(log::max_level().into()).unwrap();
+
+    // position, volume and the rest are in the MpvPlayer global
+    mpv_player::bind_player!(App, &app, &widget).unwrap();
+
+    // callbacks run on the event loop thread, so they can touch the app
+    let app_weak_ = app_weak.clone();
+    widget.on_event(move |event| {
+        use mpv::event::MpvEvent;
+        let Some(app) = app_weak_.upgrade() else {
+            return;
+        };
+        match event {
+            MpvEvent::LogMessage(msg) => mpv::logging::forward(&msg),
+            MpvEvent::StartFile { .. } => app.set_error_message("".into()),
+            MpvEvent::EndFile {
+                reason: mpv::event::EndFileReason::Error,
+                error,
+                ..
+            } => {
+                let error = error.unwrap_or(mpv::ErrorKind::Generic);
+                app.set_error_message(format!("Could not play the file: {}", error).into());
+            }
+            _ => {}
+        }
+    });
+
+    let mpv_ = mpv.clone();
+    app.on_open_file(move || {
+        if let Some(path) = rfd::FileDialog::new().pick_file() {
+            let cmd = mpv::command::Command::load_file(&path);
+            if let Err(e) = mpv_.command(&cmd) {
+                log::warn!("Could not open {}: {}", path.display(), e);
+            }
+        }
+    });
+
+    // video output waits for a render context, so this is fine to do early
+    mpv.command(&mpv::command::Command::load_file(
+        "http://commondatastorage.googleapis.com/gtv-videos-bucket/sample/TearsOfSteel.mp4",
+    ))
+    .unwrap();
+
+    app.run().unwrap();
+}
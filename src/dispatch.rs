@@ -0,0 +1,94 @@
+//! Reading mpv events on the slint event loop, without a polling thread
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::mpv::event::{Events, MpvEvent};
+
+type Handler = Box<dyn FnMut(MpvEvent) + Send>;
+
+struct State {
+    /// Locked together, as handler is only called while reading events
+    inner: Mutex<(Events, Handler)>,
+    /// Whether a drain is already queued in the event loop. Mpv calls wakeup
+    /// for every event, and we don't want a closure per event.
+    scheduled: AtomicBool,
+}
+
+/// Calls the handler on the slint event loop thread for every event mpv
+/// emits, so the handler can upgrade component weak handles directly. Events
+/// stop after [`MpvEvent::Shutdown`] or when this is dropped.
+pub struct Dispatcher {
+    state: Arc<State>,
+}
+
+impl Dispatcher {
+    pub fn new<F>(events: Events, handler: F) -> Self
+    where
+        F: FnMut(MpvEvent) + Send + 'static,
+    {
+        let state = Arc::new(State {
+            inner: Mutex::new((events, Box::new(handler))),
+            scheduled: AtomicBool::new(false),
+        });
+        // Weak, as mpv owns the callback, and this would make a cycle
+        let weak = Arc::downgrade(&state);
+        let mut inner = state.inner.lock().unwrap();
+        inner.0.set_wakeup_callback(move || schedule(&weak));
+        drop(inner);
+        // events queued before we've set the callback don't cause a wakeup
+        schedule(&Arc::downgrade(&state));
+        Self { state }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.state.inner.lock().unwrap().0.unset_wakeup_callback();
+    }
+}
+
+fn schedule(state: &Weak<State>) {
+    let Some(state) = state.upgrade() else {
+        return;
+    };
+    if !state.scheduled.swap(true, Ordering::AcqRel) {
+        let weak = Arc::downgrade(&state);
+        // only fails when the event loop is gone, then nobody needs events
+        let _ = slint::invoke_from_event_loop(move || drain(weak));
+    }
+}
+
+fn drain(state: Weak<State>) {
+    let Some(state) = state.upgrade() else {
+        return;
+    };
+    // reset before reading, so that events arriving while we read schedule
+    // another drain
+    state.scheduled.store(false, Ordering::Release);
+    let mut inner = state.inner.lock().unwrap();
+    let (events, handler) = &mut *inner;
+    while let Some(event) = events.wait_event(0.0) {
+        let shutdown = matches!(event, MpvEvent::Shutdown);
+        handler(event);
+        if shutdown {
+            events.unset_wakeup_callback();
+            break;
+        }
+    }
+}
+
+/// Wrap a callback to run on the slint event loop, for when events are read
+/// on some other thread, like with
+/// [`Observers::on_change`](crate::mpv::observers::Observers::on_change)
+pub fn in_event_loop<T, F>(f: F) -> impl FnMut(T) + Send + 'static
+where
+    T: Send + 'static,
+    F: Fn(T) + Send + Sync + 'static,
+{
+    let f = Arc::new(f);
+    move |value| {
+        let f = f.clone();
+        let _ = slint::invoke_from_event_loop(move || f(value));
+    }
+}
@@ -28,18 +28,61 @@ use std::ffi::c_void;
 #[allow(non_camel_case_types)]
 #[allow(non_upper_case_globals)]
 #[allow(non_snake_case)]
+#[cfg_attr(test, path = "mpv/stub.rs")]
 mod sys;
 
+#[cfg(not(test))]
+#[link(name = "mpv")]
+extern "C" {}
+
+pub mod builder;
+pub mod command;
+pub mod error;
+pub mod hooks;
+pub mod logging;
+pub mod messages;
+pub mod node;
+pub mod observers;
+pub mod reply;
+pub mod software;
+pub mod stream;
+#[cfg(test)]
+mod tests;
+
 pub struct Mpv {
     ptr: *mut sys::mpv_handle,
+    /// Whether dropping this stops the whole player, or only this client
+    terminate: bool,
+    /// Source of `reply_userdata` values
+    next_id: std::sync::atomic::AtomicU64,
+    pending: reply::Pending,
+    hooks: hooks::Hooks,
+    /// Boxed so that mpv can keep a pointer to it
+    wakeup: Box<CallbackSlot>,
+    /// Protocols stay registered with the player until it's terminated, so
+    /// only the handle that terminates it keeps these, see
+    /// [`add_stream_protocol`](Self::add_stream_protocol)
+    stream_providers: std::sync::Mutex<Vec<Box<dyn std::any::Any + Send + Sync>>>,
 }
 
+/// Calls are serialized by the mutex, so callbacks don't need to be `Sync`
+type Callback = Box<dyn Fn() + Send>;
+type CallbackSlot = std::sync::Mutex<Option<Callback>>;
+
 impl Drop for Mpv {
     fn drop(&mut self) {
-        unsafe { sys::mpv_terminate_destroy(self.ptr) }
+        if self.terminate {
+            unsafe { sys::mpv_terminate_destroy(self.ptr) }
+        } else {
+            unsafe { sys::mpv_destroy(self.ptr) }
+        }
     }
 }
 
+pub use builder::MpvBuilder;
+pub use error::{Error, ErrorKind};
+pub use software::SwRenderContext;
+
 type Result<T> = std::result::Result<T, Error>;
 
 // Safety: mpv docs say: "concurrent calls to different mpv_handles are always safe"
@@ -47,64 +90,254 @@ unsafe impl Send for Mpv {}
 unsafe impl Sync for Mpv {}
 
 impl Mpv {
-    /// Two-step creation: new, set options, [`initialize`]
-    #[must_use]
-    pub fn new() -> Option<Self> {
+    /// Create and configure with [`MpvBuilder`]
+    pub fn builder() -> Result<MpvBuilder> {
+        MpvBuilder::new()
+    }
+
+    /// Uninitialized handle, for the builder
+    fn new() -> Option<Self> {
         let ptr = unsafe { sys::mpv_create() };
+        // Safety: just created
+        unsafe { Self::from_ptr(ptr, true) }
+    }
+
+    /// Safety: `ptr` must be a handle nobody else owns
+    unsafe fn from_ptr(ptr: *mut sys::mpv_handle, terminate: bool) -> Option<Self> {
         if ptr.is_null() {
-            None
-        } else {
-            Some(Self { ptr })
+            return None;
         }
+        let wakeup = Box::new(CallbackSlot::default());
+        // Safety: the slot outlives the handle, as it's only dropped after
+        // the handle is destroyed
+        let slot_ptr = &*wakeup as *const CallbackSlot as *mut c_void;
+        sys::mpv_set_wakeup_callback(ptr, Some(call_slot), slot_ptr);
+        Some(Self {
+            ptr,
+            terminate,
+            next_id: std::sync::atomic::AtomicU64::new(1),
+            pending: reply::Pending::default(),
+            hooks: hooks::Hooks::default(),
+            stream_providers: Default::default(),
+            wakeup,
+        })
     }
 
-    pub fn set_option_string(&self, name: &str, value: &str) -> Result<()> {
-        // Safety: it's just cstrings
-        let e = unsafe {
-            let name = std::ffi::CString::new(name).unwrap();
-            let value = std::ffi::CString::new(value).unwrap();
-            sys::mpv_set_option_string(self.ptr, name.as_ptr(), value.as_ptr())
-        };
-        Error::raise(e)
+    /// Another handle to the same player, with its own events, observed
+    /// properties and hooks. Dropping it only detaches the client, but the
+    /// player keeps running until every non-weak handle is gone. Dropping a
+    /// handle made by [`MpvBuilder`] stops the player for all clients.
+    ///
+    /// Comes with the only event queue of the new handle.
+    pub fn create_client(&self, name: &str) -> Result<(std::sync::Arc<Mpv>, event::Events)> {
+        self.create_client_with(name, "create_client", sys::mpv_create_client)
+    }
+
+    /// Like [`create_client`](Self::create_client), but doesn't keep the
+    /// player alive. It gets [`MpvEvent::Shutdown`](event::MpvEvent::Shutdown)
+    /// when the last strong handle is dropped.
+    pub fn create_weak_client(&self, name: &str) -> Result<(std::sync::Arc<Mpv>, event::Events)> {
+        self.create_client_with(name, "create_weak_client", sys::mpv_create_weak_client)
     }
 
-    /// Must call this before doing anything interesting
-    pub fn initialize(&self) -> Result<()> {
+    fn create_client_with(
+        &self,
+        name: &str,
+        operation: &'static str,
+        create: unsafe extern "C" fn(
+            *mut sys::mpv_handle,
+            *const std::ffi::c_char,
+        ) -> *mut sys::mpv_handle,
+    ) -> Result<(std::sync::Arc<Mpv>, event::Events)> {
+        let context = |e: Error| e.context(operation, Some(name));
+        let c_name = std::ffi::CString::new(name).map_err(|e| context(e.into()))?;
+        // Safety: the new handle is only owned by the returned value
+        unsafe {
+            let ptr = create(self.ptr, c_name.as_ptr());
+            // no reason given here either
+            let mpv =
+                Self::from_ptr(ptr, false).ok_or_else(|| context(ErrorKind::Generic.into()))?;
+            Ok(mpv.with_events())
+        }
+    }
+
+    /// Share a new handle, and hand out its event queue. Only called once
+    /// per handle, which is what makes the queue single-consumer.
+    fn with_events(self) -> (std::sync::Arc<Mpv>, event::Events) {
+        let mpv = std::sync::Arc::new(self);
+        (mpv.clone(), event::Events::new(mpv))
+    }
+
+    /// Name of this client, unique in the player. Mpv adds a number to
+    /// names that are taken.
+    pub fn client_name(&self) -> String {
+        // Safety: valid as long as the handle
+        let name = unsafe { std::ffi::CStr::from_ptr(sys::mpv_client_name(self.ptr)) };
+        name.to_string_lossy().into_owned()
+    }
+
+    fn initialize(&self) -> Result<()> {
         let e = unsafe { sys::mpv_initialize(self.ptr) };
-        Error::raise(e)
+        Error::raise(e).map_err(|e| e.context("initialize", None))
     }
 
     /// See https://mpv.io/manual/master/#list-of-input-commands
-    pub fn command(&self, args: &[&str]) -> Result<()> {
-        let args_buf = args
-            .iter()
-            .map(|s| std::ffi::CString::new(*s).unwrap())
-            .collect::<Vec<_>>();
-        let mut args = args_buf.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
-        args.push(std::ptr::null());
-        // Safety: TODO
-        let e = unsafe { sys::mpv_command(self.ptr, args.as_mut_ptr()) };
-        Error::raise(e)
+    pub fn command(&self, cmd: &command::Command) -> Result<()> {
+        self.command_ret(cmd).map(drop)
+    }
+
+    /// Run a command and return whatever it produced. Most commands return
+    /// [`MpvNode::None`](node::MpvNode::None)
+    pub fn command_ret(&self, cmd: &command::Command) -> Result<node::MpvNode> {
+        use property::Repr;
+        let args = cmd
+            .to_node()
+            .to_raw()
+            .map_err(|e| e.context("command", Some(cmd.name())))?;
+        let mut result = sys::mpv_node::default();
+        // Safety: args is a valid node, and result is written only on success
+        let e = unsafe {
+            let e = sys::mpv_command_node(self.ptr, &args as *const _ as *mut _, &mut result);
+            args.free_from_rust();
+            e
+        };
+        Error::raise(e).map_err(|e| e.context("command", Some(cmd.name())))?;
+        // Safety: filled by mpv on success
+        unsafe {
+            let value = node::MpvNode::from_raw(&result);
+            result.free_from_mpv();
+            Ok(value)
+        }
+    }
+
+    pub fn command_typed<C: command::TypedCommand>(&self, cmd: &C) -> Result<C::Output> {
+        let cmd = cmd.command();
+        let result = self.command_ret(&cmd)?;
+        C::decode(result).map_err(|e| Error::from(e).context("command", Some(cmd.name())))
+    }
+
+    /// Like [`command_ret`](Self::command_ret), but doesn't block
+    pub fn command_async(&self, cmd: &command::Command) -> reply::ReplyFuture<'_, node::MpvNode> {
+        self.command_async_with(cmd, Ok)
+    }
+
+    pub fn command_typed_async<C: command::TypedCommand>(
+        &self,
+        cmd: &C,
+    ) -> reply::ReplyFuture<'_, C::Output>
+    where
+        C::Output: Send + 'static,
+    {
+        self.command_async_with(&cmd.command(), |result| {
+            C::decode(result).map_err(Error::from)
+        })
+    }
+
+    fn command_async_with<T, F>(
+        &self,
+        cmd: &command::Command,
+        decode: F,
+    ) -> reply::ReplyFuture<'_, T>
+    where
+        T: Send + 'static,
+        F: FnOnce(node::MpvNode) -> Result<T> + Send + 'static,
+    {
+        use property::Repr;
+        let id = self.next_id();
+        let name = cmd.name();
+        let name_ = name.to_owned();
+        let future = reply::ReplyFuture::new(self, id, reply::Kind::Command, move |e| {
+            let context = |e: Error| e.context("command", Some(&name_));
+            Error::raise(e.error).map_err(context)?;
+            let data = e.data as *const sys::mpv_event_command;
+            debug_assert!(!data.is_null());
+            // Safety: reply to a command always has this data
+            let result = unsafe { node::MpvNode::from_raw(&(*data).result) };
+            decode(result).map_err(context)
+        });
+        let args = match cmd.to_node().to_raw() {
+            Ok(args) => args,
+            Err(e) => return future.fail(e.context("command", Some(name))),
+        };
+        // Safety: args is a valid node, and mpv copies it before returning
+        let e = unsafe {
+            let e = sys::mpv_command_node_async(self.ptr, id, &args as *const _ as *mut _);
+            args.free_from_rust();
+            e
+        };
+        match Error::raise(e) {
+            Ok(()) => future,
+            Err(e) => future.fail(e.context("command", Some(name))),
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
     }
 }
 
-/// GL render context
-pub struct MpvRenderContext {
+/// What the GL and software render contexts have in common: the context,
+/// its update callback and the core it renders
+struct RawRenderContext {
     ptr: *mut sys::mpv_render_context,
+    /// Registered with mpv for the whole life of the context, and only freed
+    /// after it
+    update: Box<CallbackSlot>,
     parent: std::sync::Arc<Mpv>,
 }
 
-impl Drop for MpvRenderContext {
+impl Drop for RawRenderContext {
     fn drop(&mut self) {
+        // Safety: mpv guarantees no update callbacks run after this returns
         unsafe { sys::mpv_render_context_free(self.ptr) };
     }
 }
 
+impl RawRenderContext {
+    /// `params` end with an invalid one, and are only used during the call
+    fn new(parent: std::sync::Arc<Mpv>, params: &mut [sys::mpv_render_param]) -> Result<Self> {
+        let mut ptr = std::ptr::null_mut();
+        let e =
+            unsafe { sys::mpv_render_context_create(&mut ptr, parent.ptr, params.as_mut_ptr()) };
+        Error::raise(e).map_err(|e| e.context("render_context_create", None))?;
+        let update = Box::new(CallbackSlot::default());
+        let slot_ptr = &*update as *const CallbackSlot as *mut c_void;
+        // Safety: the context is new, and we free it before the slot
+        unsafe { sys::mpv_render_context_set_update_callback(ptr, Some(call_slot), slot_ptr) };
+        Ok(Self {
+            ptr,
+            update,
+            parent,
+        })
+    }
+
+    fn set_update_callback(&mut self, cb: Callback) {
+        *lock(&self.update) = Some(cb);
+    }
+
+    fn unset_update_callback(&mut self) {
+        *lock(&self.update) = None;
+    }
+
+    /// `params` end with an invalid one
+    fn render(&mut self, params: &mut [sys::mpv_render_param]) -> Result<()> {
+        let e = unsafe { sys::mpv_render_context_render(self.ptr, params.as_mut_ptr()) };
+        Error::raise(e).map_err(|e| e.context("render", None))
+    }
+}
+
+/// GL render context. Must be used on the thread with the GL context it was
+/// created for.
+pub struct MpvRenderContext {
+    inner: RawRenderContext,
+}
+
 impl std::ops::Deref for MpvRenderContext {
     type Target = Mpv;
 
     fn deref(&self) -> &Mpv {
-        &self.parent
+        &self.inner.parent
     }
 }
 
@@ -113,22 +346,25 @@ impl std::ops::Deref for MpvRenderContext {
 pub type CreateContextFn<'a> = dyn Fn(&std::ffi::CStr) -> *const c_void + 'a;
 
 impl MpvRenderContext {
-    pub fn new<'a>(
-        parent: std::sync::Arc<Mpv>,
-        get_proc_addr: &'a &CreateContextFn<'a>,
-    ) -> Result<Self> {
+    /// `get_proc_addr` is only called during this function
+    pub fn new(parent: std::sync::Arc<Mpv>, get_proc_addr: &CreateContextFn<'_>) -> Result<Self> {
         // this is monomorphic because it's only ever used for slint's function
         // type, and doing otherwise would require too many plumbing, not worth
-        unsafe extern "C" fn call_closure(closure_ptr: *mut c_void, arg: *const i8) -> *mut c_void {
+        unsafe extern "C" fn call_closure(
+            closure_ptr: *mut c_void,
+            arg: *const std::ffi::c_char,
+        ) -> *mut c_void {
             let arg = std::ffi::CStr::from_ptr(arg);
-            let closure_ptr = closure_ptr as *const &CreateContextFn;
-            let closure: &&CreateContextFn = &*closure_ptr;
-            closure(arg).cast_mut()
+            let closure = &*(closure_ptr as *const &CreateContextFn);
+            // can't unwind into mpv, and null is what it expects on failure
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| closure(arg).cast_mut()))
+                .unwrap_or(std::ptr::null_mut())
         }
-        let closure_ptr = get_proc_addr as *const &CreateContextFn;
+        // thin pointer to the fat one, living on the stack until we return
+        let get_proc_addr: &&CreateContextFn = &get_proc_addr;
         let mut init_params = sys::mpv_opengl_init_params {
             get_proc_address: Some(call_closure),
-            get_proc_address_ctx: closure_ptr as *mut c_void,
+            get_proc_address_ctx: get_proc_addr as *const &CreateContextFn as *mut c_void,
         };
         let mut params = [
             sys::mpv_render_param {
@@ -144,32 +380,22 @@ impl MpvRenderContext {
                 data: std::ptr::null_mut(),
             },
         ];
-
-        let mut ptr = std::ptr::null_mut();
-        let e =
-            unsafe { sys::mpv_render_context_create(&mut ptr, parent.ptr, params.as_mut_ptr()) };
-        Error::raises(Self { ptr, parent }, e)
+        let inner = RawRenderContext::new(parent, &mut params)?;
+        Ok(Self { inner })
     }
 
-    #[allow(dead_code)]
     pub fn unset_update_callback(&mut self) {
-        unsafe { sys::mpv_render_context_set_update_callback(self.ptr, None, std::ptr::null_mut()) }
+        self.inner.unset_update_callback()
     }
 
+    /// Called from an mpv thread when a new frame should be rendered. Like
+    /// the wakeup callback, it must not call any mpv functions, and only
+    /// signal the render thread. Replaces the previous callback.
     pub fn set_update_callback<F>(&mut self, cb: F)
     where
-        F: FnMut(),
+        F: Fn() + Send + 'static,
     {
-        let closure = Box::new(cb);
-        unsafe {
-            let closure_ptr = Box::into_raw(closure);
-            let closure_ptr = closure_ptr as *mut c_void;
-            sys::mpv_render_context_set_update_callback(
-                self.ptr,
-                Some(call_closure_0::<F>),
-                closure_ptr,
-            )
-        }
+        self.inner.set_update_callback(Box::new(cb))
     }
 
     pub fn render(&mut self, fbo: u32, width: i32, height: i32) -> Result<()> {
@@ -194,8 +420,7 @@ impl MpvRenderContext {
                 data: std::ptr::null_mut(),
             },
         ];
-        let e = unsafe { sys::mpv_render_context_render(self.ptr, params.as_mut_ptr()) };
-        Error::raise(e)
+        self.inner.render(&mut params)
     }
 }
 
@@ -203,160 +428,353 @@ impl MpvRenderContext {
 ///
 /// See https://mpv.io/manual/master/#properties
 pub mod property {
-    use std::ffi::CStr;
+    use std::ffi::{c_char, c_int, CStr, CString, OsString};
 
-    use super::sys;
+    use super::node::{self, MpvNode};
+    use super::{sys, Error};
 
-    /// Only actually used when listening for events.
-    #[derive(Debug, Clone)]
-    pub enum Property {
-        Duration(Duration),
-        TimePos(TimePos),
-        Pause(Pause),
-        AoVolume(AoVolume),
-        AoMute(AoMute),
-        Filename(Filename),
-    }
-
-    #[derive(Debug, Clone, Copy)]
-    pub struct Duration(pub f64);
-    #[derive(Debug, Clone, Copy)]
-    pub struct TimePos(pub f64);
-    /// This one is not documented?? But it's mentioned in a lot of examples?
-    #[derive(Debug, Clone, Copy)]
-    pub struct Pause(pub bool);
-    #[derive(Debug, Clone, Copy)]
-    /// Until a video with audio track is loaded, volume property is not
-    /// accessible. And even for some time after loading too.
-    pub struct AoVolume(pub f64);
-    #[derive(Debug, Clone, Copy)]
-    pub struct AoMute(pub bool);
-    #[derive(Debug, Clone)]
-    pub struct Filename(pub String);
+    /// Declares property structs together with the [`Property`] enum. Each
+    /// entry is `Struct = "mpv-name": Type, access;`, where `Type` implements
+    /// [`Value`] and `access` is `read_only` or `read_write`. Attributes go to
+    /// the struct.
+    macro_rules! properties {
+        (@read_only $name:ident) => {};
+        (@read_write $name:ident) => {
+            impl WriteProperty for $name {
+                fn to_repr(&self) -> Result<Self::MpvRepr, Error> {
+                    Value::to_repr(&self.0)
+                }
+            }
+        };
+        ($(
+            $(#[$meta:meta])*
+            $name:ident = $mpv_name:literal: $ty:ty, $access:ident;
+        )*) => {
+            /// Only actually used when listening for events.
+            #[derive(Debug, Clone)]
+            pub enum Property {
+                $($name($name),)*
+                /// Any property without a variant here. Use [`Property::value`] to
+                /// get a typed value out of it.
+                Other {
+                    name: String,
+                    /// [`MpvNode::None`] when the property is unavailable
+                    value: MpvNode,
+                },
+            }
+
+            $(
+                $(#[$meta])*
+                #[derive(Debug, Clone)]
+                pub struct $name(pub $ty);
+
+                impl ReadProperty for $name {
+                    const NAME: &'static CStr =
+                        match CStr::from_bytes_with_nul(concat!($mpv_name, "\0").as_bytes()) {
+                            Ok(name) => name,
+                            Err(_) => panic!(concat!("nul in property name ", $mpv_name)),
+                        };
+                    const FORMAT: sys::mpv_format = <$ty as Value>::FORMAT;
+                    type MpvRepr = <$ty as Value>::MpvRepr;
+                    fn from_repr(val: Self::MpvRepr) -> Self {
+                        Self(<$ty as Value>::from_repr(val))
+                    }
+                }
+
+                properties!(@$access $name);
+
+            )*
+
+            impl Property {
+                /// [`ConvertError::Invalid`] if there is no variant for `name`
+                unsafe fn read_known(
+                    name: &CStr,
+                    prop: *const sys::mpv_event_property,
+                ) -> Result<Self, ConvertError> {
+                    $(
+                        if name == $name::NAME {
+                            return read(prop).map(Property::$name);
+                        }
+                    )*
+                    Err(ConvertError::Invalid)
+                }
+
+                /// Name and value, the way [`Property::Other`] holds them
+                fn into_parts(self) -> (String, MpvNode) {
+                    match self {
+                        $(Property::$name(x) => ($mpv_name.to_owned(), x.0.into()),)*
+                        Property::Other { name, value } => (name, value),
+                    }
+                }
+            }
+        };
+    }
+
+    properties! {
+        #[derive(Copy)]
+        Duration = "duration": f64, read_only;
+        #[derive(Copy)]
+        TimePos = "time-pos": f64, read_write;
+        #[derive(Copy)]
+        TimeRemaining = "time-remaining": f64, read_only;
+        #[derive(Copy)]
+        PlaybackTime = "playback-time": f64, read_write;
+        #[derive(Copy)]
+        PercentPos = "percent-pos": f64, read_write;
+        #[derive(Copy)]
+        Speed = "speed": f64, read_write;
+        /// This one is not documented?? But it's mentioned in a lot of examples?
+        #[derive(Copy)]
+        Pause = "pause": bool, read_write;
+        #[derive(Copy)]
+        PausedForCache = "paused-for-cache": bool, read_only;
+        #[derive(Copy)]
+        CoreIdle = "core-idle": bool, read_only;
+        #[derive(Copy)]
+        IdleActive = "idle-active": bool, read_only;
+        #[derive(Copy)]
+        EofReached = "eof-reached": bool, read_only;
+        #[derive(Copy)]
+        Seeking = "seeking": bool, read_only;
+        #[derive(Copy)]
+        Seekable = "seekable": bool, read_only;
+        /// Until a video with audio track is loaded, volume property is not
+        /// accessible. And even for some time after loading too.
+        #[derive(Copy)]
+        AoVolume = "ao-volume": f64, read_write;
+        #[derive(Copy)]
+        AoMute = "ao-mute": bool, read_write;
+        /// Software volume, in percent. Unlike [`AoVolume`] it's always there.
+        #[derive(Copy)]
+        Volume = "volume": f64, read_write;
+        #[derive(Copy)]
+        Mute = "mute": bool, read_write;
+        /// Seconds
+        #[derive(Copy)]
+        AudioDelay = "audio-delay": f64, read_write;
+        /// Seconds
+        #[derive(Copy)]
+        SubDelay = "sub-delay": f64, read_write;
+        /// Bytes as mpv has them, use `to_string_lossy` for display. Same for
+        /// the other file names and titles.
+        Filename = "filename": OsString, read_only;
+        /// Path or url as it was given to mpv
+        Path = "path": OsString, read_only;
+        /// Usually from metadata, and the file name otherwise. Not
+        /// necessarily valid UTF-8 either.
+        MediaTitle = "media-title": OsString, read_only;
+        /// What actually gets opened. Can be set in the `on_load` hook to
+        /// rewrite the url.
+        StreamOpenFilename = "stream-open-filename": OsString, read_write;
+        /// Bytes
+        #[derive(Copy)]
+        FileSize = "file-size": i64, read_only;
+        /// Video size in pixels, before any filters or aspect correction
+        #[derive(Copy)]
+        Width = "width": i64, read_only;
+        #[derive(Copy)]
+        Height = "height": i64, read_only;
+        /// Current chapter, starting from zero
+        #[derive(Copy)]
+        Chapter = "chapter": i64, read_write;
+        #[derive(Copy)]
+        Chapters = "chapters": i64, read_only;
+        /// Current playlist entry, starting from zero, or -1 when none
+        #[derive(Copy)]
+        PlaylistPos = "playlist-pos": i64, read_write;
+        #[derive(Copy)]
+        PlaylistCount = "playlist-count": i64, read_only;
+        /// Array of maps with `id`, `type`, `title`, `lang`, `selected` and more
+        TrackList = "track-list": MpvNode, read_only;
+        /// Array of maps with `title` and `time`
+        ChapterList = "chapter-list": MpvNode, read_only;
+        /// Map of tag names to strings
+        Metadata = "metadata": MpvNode, read_only;
+        /// Array of maps with `filename`, `current`, `playing`, `title` and `id`
+        Playlist = "playlist": MpvNode, read_only;
+    }
+
+    macro_rules! lossy_strings {
+        ($($name:ident),*) => {$(
+            impl $name {
+                /// For display, with invalid UTF-8 replaced
+                pub fn to_string_lossy(&self) -> std::borrow::Cow<'_, str> {
+                    self.0.to_string_lossy()
+                }
+            }
+        )*};
+    }
+    lossy_strings!(Filename, Path, MediaTitle, StreamOpenFilename);
+
+    /// Formats for [`ReadProperty::FORMAT`], with the matching
+    /// [`ReadProperty::MpvRepr`] in docs
+    pub mod format {
+        use super::sys;
+        /// [`StrPtr`](super::StrPtr)
+        pub const STRING: sys::mpv_format = sys::mpv_format_MPV_FORMAT_STRING;
+        /// `c_int`, zero or one
+        pub const FLAG: sys::mpv_format = sys::mpv_format_MPV_FORMAT_FLAG;
+        /// `i64`
+        pub const INT64: sys::mpv_format = sys::mpv_format_MPV_FORMAT_INT64;
+        /// `f64`
+        pub const DOUBLE: sys::mpv_format = sys::mpv_format_MPV_FORMAT_DOUBLE;
+        /// [`RawNode`](crate::mpv::node::RawNode)
+        pub const NODE: sys::mpv_format = sys::mpv_format_MPV_FORMAT_NODE;
+    }
 
     pub trait ReadProperty: Sized {
         const NAME: &'static CStr;
         const FORMAT: sys::mpv_format;
-        type MpvRepr: Default + Copy;
+        type MpvRepr: Repr;
         fn from_repr(val: Self::MpvRepr) -> Self;
     }
     pub trait WriteProperty: ReadProperty {
-        fn to_repr(&self) -> Self::MpvRepr;
+        /// If the repr points to memory, it's allocated here and freed with
+        /// [`Repr::free_from_rust`] after mpv has copied it. Fails with
+        /// [`InteriorNul`](super::ErrorKind::InteriorNul) for strings that
+        /// mpv would cut short.
+        fn to_repr(&self) -> Result<Self::MpvRepr, Error>;
     }
 
-    impl Property {
-        pub unsafe fn from_raw(prop: *const sys::mpv_event_property) -> Result<Self, ConvertError> {
-            debug_assert!(!prop.is_null());
-
-            unsafe fn read<P: ReadProperty>(
-                p: *const sys::mpv_event_property,
-            ) -> Result<P, ConvertError> {
-                if (*p).format == P::FORMAT {
-                    let data = (*p).data as *const P::MpvRepr;
-                    debug_assert!(!data.is_null());
-                    Ok(P::from_repr(*data))
-                } else {
-                    Err(ConvertError::TypeError)
-                }
-            }
-
-            let name = std::ffi::CStr::from_ptr((*prop).name);
-            if name == Duration::NAME {
-                read(prop).map(Property::Duration)
-            } else if name == TimePos::NAME {
-                read(prop).map(Property::TimePos)
-            } else if name == Pause::NAME {
-                read(prop).map(Property::Pause)
-            } else if name == AoVolume::NAME {
-                read(prop).map(Property::AoVolume)
-            } else if name == AoMute::NAME {
-                read(prop).map(Property::AoMute)
-            } else if name == Filename::NAME {
-                read(prop).map(Property::Filename)
-            } else {
-                Err(ConvertError::Invalid)
-            }
-        }
+    /// Rust types that property structs wrap, with the mpv format each one
+    /// is read and written in
+    pub trait Value: Sized + Into<MpvNode> {
+        const FORMAT: sys::mpv_format;
+        type MpvRepr: Repr;
+        fn from_repr(val: Self::MpvRepr) -> Self;
+        /// Same as [`WriteProperty::to_repr`]
+        fn to_repr(&self) -> Result<Self::MpvRepr, Error>;
     }
-
-    impl ReadProperty for Duration {
-        const NAME: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"duration\0") };
-        const FORMAT: sys::mpv_format = sys::mpv_format_MPV_FORMAT_DOUBLE;
+    impl Value for f64 {
+        const FORMAT: sys::mpv_format = format::DOUBLE;
         type MpvRepr = f64;
-        fn from_repr(val: Self::MpvRepr) -> Self {
-            Self(val)
+        fn from_repr(val: f64) -> Self {
+            val
         }
-    }
-    impl WriteProperty for Duration {
-        fn to_repr(&self) -> Self::MpvRepr {
-            self.0
+        fn to_repr(&self) -> Result<f64, Error> {
+            Ok(*self)
         }
     }
-    impl ReadProperty for TimePos {
-        const NAME: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"time-pos\0") };
-        const FORMAT: sys::mpv_format = sys::mpv_format_MPV_FORMAT_DOUBLE;
-        type MpvRepr = f64;
-        fn from_repr(val: Self::MpvRepr) -> Self {
-            Self(val)
+    impl Value for i64 {
+        const FORMAT: sys::mpv_format = format::INT64;
+        type MpvRepr = i64;
+        fn from_repr(val: i64) -> Self {
+            val
         }
-    }
-    impl WriteProperty for TimePos {
-        fn to_repr(&self) -> Self::MpvRepr {
-            self.0
+        fn to_repr(&self) -> Result<i64, Error> {
+            Ok(*self)
         }
     }
-    impl ReadProperty for Pause {
-        const NAME: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"pause\0") };
-        const FORMAT: sys::mpv_format = sys::mpv_format_MPV_FORMAT_FLAG;
-        type MpvRepr = std::ffi::c_int;
-        fn from_repr(val: Self::MpvRepr) -> Self {
-            Self(val != 0)
+    impl Value for bool {
+        const FORMAT: sys::mpv_format = format::FLAG;
+        type MpvRepr = c_int;
+        fn from_repr(val: c_int) -> Self {
+            val != 0
         }
-    }
-    impl WriteProperty for Pause {
-        fn to_repr(&self) -> Self::MpvRepr {
-            std::ffi::c_int::from(self.0)
+        fn to_repr(&self) -> Result<c_int, Error> {
+            Ok(c_int::from(*self))
         }
     }
-    impl ReadProperty for AoVolume {
-        const NAME: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"ao-volume\0") };
-        const FORMAT: sys::mpv_format = sys::mpv_format_MPV_FORMAT_DOUBLE;
-        type MpvRepr = f64;
-        fn from_repr(val: Self::MpvRepr) -> Self {
-            Self(val)
+    impl Value for String {
+        const FORMAT: sys::mpv_format = format::STRING;
+        type MpvRepr = StrPtr;
+        fn from_repr(val: StrPtr) -> Self {
+            // Or should I do bytestring and convert to bytes instead of lossy?
+            // Or should I assume unicode and unwrap?
+            unsafe { val.as_c_str() }.to_string_lossy().into_owned()
+        }
+        fn to_repr(&self) -> Result<StrPtr, Error> {
+            Ok(StrPtr(CString::new(self.as_str())?.into_raw()))
         }
     }
-    impl WriteProperty for AoVolume {
-        fn to_repr(&self) -> Self::MpvRepr {
-            self.0
+    /// Keeps the bytes of strings that aren't valid UTF-8, like file names
+    impl Value for OsString {
+        const FORMAT: sys::mpv_format = format::STRING;
+        type MpvRepr = StrPtr;
+        fn from_repr(val: StrPtr) -> Self {
+            node::os_string_from_bytes(unsafe { val.as_c_str() }.to_bytes())
+        }
+        fn to_repr(&self) -> Result<StrPtr, Error> {
+            Ok(StrPtr(CString::new(node::os_str_bytes(self))?.into_raw()))
         }
     }
-    impl ReadProperty for AoMute {
-        const NAME: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"ao-mute\0") };
-        const FORMAT: sys::mpv_format = sys::mpv_format_MPV_FORMAT_FLAG;
-        type MpvRepr = std::ffi::c_int;
-        fn from_repr(val: Self::MpvRepr) -> Self {
-            Self(val != 0)
+    impl Value for MpvNode {
+        const FORMAT: sys::mpv_format = format::NODE;
+        type MpvRepr = sys::mpv_node;
+        fn from_repr(val: sys::mpv_node) -> Self {
+            unsafe { MpvNode::from_raw(&val) }
         }
+        fn to_repr(&self) -> Result<sys::mpv_node, Error> {
+            self.to_raw()
+        }
+    }
+
+    /// C representation of a value of some mpv format. Some formats point to
+    /// memory, which needs to be freed by whoever allocated it.
+    pub trait Repr: Default + Copy {
+        /// Free memory allocated by mpv, like when filled by
+        /// `mpv_get_property`.
+        ///
+        /// # Safety
+        ///
+        /// Only once, and only for values mpv gave us. Values inside events
+        /// are owned by the event and must not be freed.
+        unsafe fn free_from_mpv(self) {}
+        /// Free memory allocated in [`WriteProperty::to_repr`]
+        ///
+        /// # Safety
+        ///
+        /// Only once, and only for values from `to_repr`
+        unsafe fn free_from_rust(self) {}
     }
-    impl WriteProperty for AoMute {
-        fn to_repr(&self) -> Self::MpvRepr {
-            std::ffi::c_int::from(self.0)
+    impl Repr for f64 {}
+    impl Repr for i64 {}
+    impl Repr for c_int {}
+
+    unsafe fn read<P: ReadProperty>(p: *const sys::mpv_event_property) -> Result<P, ConvertError> {
+        if (*p).format == P::FORMAT {
+            let data = (*p).data as *const P::MpvRepr;
+            debug_assert!(!data.is_null());
+            Ok(P::from_repr(*data))
+        } else {
+            Err(ConvertError::TypeError)
         }
     }
-    impl ReadProperty for Filename {
-        const NAME: &'static CStr = unsafe { CStr::from_bytes_with_nul_unchecked(b"filename\0") };
-        const FORMAT: sys::mpv_format = sys::mpv_format_MPV_FORMAT_STRING;
-        type MpvRepr = StrPtr;
-        fn from_repr(val: Self::MpvRepr) -> Self {
-            let cstr = unsafe { std::ffi::CStr::from_ptr(val.0) };
-            // Or should I do bytestring and convert to bytes instead of lossy?
-            // Or should I assume unicode and unwrap?
-            Self(cstr.to_string_lossy().into_owned())
+
+    impl Property {
+        /// # Safety
+        ///
+        /// `prop` must be the data of a property event from mpv
+        pub unsafe fn from_raw(prop: *const sys::mpv_event_property) -> Result<Self, ConvertError> {
+            debug_assert!(!prop.is_null());
+            let name = CStr::from_ptr((*prop).name);
+            // unknown properties, and known ones observed in another format
+            Ok(
+                Self::read_known(name, prop).unwrap_or_else(|_| Property::Other {
+                    name: name.to_string_lossy().into_owned(),
+                    value: MpvNode::from_data((*prop).format, (*prop).data),
+                }),
+            )
+        }
+
+        /// Typed value of any property, including ones not known to this
+        /// module, which arrive as [`Property::Other`]. Decoded by name and
+        /// format, so `P` can be any type with the same name, not only the
+        /// one from this module.
+        pub fn value<P: ReadProperty>(self) -> Option<P> {
+            let (name, value) = self.into_parts();
+            if name.as_bytes() != P::NAME.to_bytes() {
+                return None;
+            }
+            value.with_data(P::FORMAT, |data| {
+                // Safety: with_data gives data in P::FORMAT, and it stays
+                // alive during the call
+                unsafe { P::from_repr(*(data as *const P::MpvRepr)) }
+            })
         }
     }
 
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum ConvertError {
         TypeError,
         Invalid,
@@ -364,43 +782,222 @@ pub mod property {
 
     #[derive(Clone, Copy)]
     #[repr(transparent)]
-    pub struct StrPtr(*const i8);
+    pub struct StrPtr(*const c_char);
     impl Default for StrPtr {
         fn default() -> Self {
             Self(std::ptr::null())
         }
     }
+    impl StrPtr {
+        /// # Safety
+        ///
+        /// Only valid inside [`ReadProperty::from_repr`], and the string must
+        /// not outlive the call
+        pub unsafe fn as_c_str<'a>(self) -> &'a CStr {
+            CStr::from_ptr(self.0)
+        }
+    }
+    impl Repr for StrPtr {
+        unsafe fn free_from_mpv(self) {
+            sys::mpv_free(self.0.cast_mut().cast())
+        }
+        unsafe fn free_from_rust(self) {
+            if !self.0.is_null() {
+                drop(std::ffi::CString::from_raw(self.0.cast_mut()))
+            }
+        }
+    }
 }
 
 /// Well-typed mpv events
 pub mod event {
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+
+    use super::{property::Property, sys, Mpv};
+
+    /// The event queue of a handle. There is only one, and reading needs
+    /// `&mut`, so nobody else can steal events from under you. It comes with
+    /// the handle from [`MpvBuilder::build`](super::MpvBuilder::build) or
+    /// [`Mpv::create_client`].
+    ///
+    /// Replies to async requests are consumed here and resolve their futures
+    /// instead of being returned.
+    pub struct Events {
+        mpv: Arc<Mpv>,
+        /// Waker of the last poll as a stream
+        waker: Arc<Mutex<Option<Waker>>>,
+        /// Whether the wakeup callback is the one that wakes the stream
+        waking_stream: bool,
+        shut_down: bool,
+    }
+
+    impl Events {
+        pub(super) fn new(mpv: Arc<Mpv>) -> Self {
+            Self {
+                mpv,
+                waker: Arc::default(),
+                waking_stream: false,
+                shut_down: false,
+            }
+        }
 
-    use super::{property::Property, sys};
+        pub fn mpv(&self) -> &Arc<Mpv> {
+            &self.mpv
+        }
+
+        pub fn wait_event(&mut self, timeout: f64) -> Option<MpvEvent> {
+            let mut timeout = timeout;
+            loop {
+                let event_ptr = unsafe { sys::mpv_wait_event(self.mpv.ptr, timeout) };
+                debug_assert!(!event_ptr.is_null());
+                // Safety: mpv always returns a valid event
+                let event = unsafe { &*event_ptr };
+                if !self.mpv.pending.resolve(event) && !self.mpv.hooks.run(&self.mpv, event) {
+                    return convert_event(event_ptr);
+                }
+                // we've already waited, so only pick up whatever else is queued
+                timeout = 0.0;
+            }
+        }
+
+        /// Called from an mpv thread when new events arrive, or when
+        /// [`Mpv::wakeup`] is called. It must not call any mpv functions,
+        /// only signal someone to read events. Replaces the previous
+        /// callback, and the one used for polling this as a stream.
+        pub fn set_wakeup_callback<F>(&mut self, cb: F)
+        where
+            F: Fn() + Send + 'static,
+        {
+            self.waking_stream = false;
+            *super::lock(&self.mpv.wakeup) = Some(Box::new(cb));
+        }
+
+        pub fn unset_wakeup_callback(&mut self) {
+            self.waking_stream = false;
+            *super::lock(&self.mpv.wakeup) = None;
+        }
+    }
+
+    impl Drop for Events {
+        fn drop(&mut self) {
+            self.unset_wakeup_callback();
+        }
+    }
+
+    /// Ends after [`MpvEvent::Shutdown`]
+    impl futures_core::Stream for Events {
+        type Item = MpvEvent;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<MpvEvent>> {
+            if self.shut_down {
+                return Poll::Ready(None);
+            }
+            if !self.waking_stream {
+                let waker = self.waker.clone();
+                self.set_wakeup_callback(move || {
+                    if let Some(waker) = waker.lock().unwrap().take() {
+                        waker.wake();
+                    }
+                });
+                self.waking_stream = true;
+            }
+            // store the waker before checking, so an event arriving in between
+            // still wakes us
+            *self.waker.lock().unwrap() = Some(cx.waker().clone());
+            match self.wait_event(0.0) {
+                Some(event) => {
+                    self.shut_down = matches!(event, MpvEvent::Shutdown);
+                    Poll::Ready(Some(event))
+                }
+                None => Poll::Pending,
+            }
+        }
+    }
 
     #[derive(Debug, Clone)]
     pub enum MpvEvent {
         StartFile {
             playlist_entry_id: i64,
         },
+        /// Playback of a file ended or it failed to load. Sent after
+        /// [`StartFile`](MpvEvent::StartFile) with the same entry id.
+        EndFile {
+            reason: EndFileReason,
+            /// Why loading or playback failed, with [`EndFileReason::Error`]
+            error: Option<super::ErrorKind>,
+            playlist_entry_id: i64,
+            /// With [`EndFileReason::Redirect`], the id of the first entry
+            /// that replaced this one. Ids of the rest follow it.
+            playlist_insert_id: i64,
+            playlist_insert_num_entries: i32,
+        },
         FileLoaded,
         PlaybackRestart,
         VideoReconfig,
         AudioReconfig,
-        PropertyChange(Property),
+        PropertyChange {
+            /// Id of the [`Subscription`](super::Subscription)
+            id: u64,
+            property: Property,
+        },
+        /// From `script-message` or `script-message-to` this client. See
+        /// [`ClientMessages`](super::messages::ClientMessages).
+        ClientMessage {
+            args: Vec<String>,
+        },
+        /// See [`logging::forward`](super::logging::forward)
+        LogMessage(super::logging::LogMessage),
+        /// Mpv is quitting, the handle should be dropped as soon as possible
+        Shutdown,
         Unsupported,
         /// Could not parse event. More events might be available
         Error,
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum EndFileReason {
+        /// End of file, or the file was empty or unsupported
+        Eof,
+        /// Stopped by something else, like playlist controls
+        Stop,
+        /// Quit command or player shutdown
+        Quit,
+        Error,
+        /// The file was a playlist, and got replaced by its entries
+        Redirect,
+        /// From a newer mpv than this module knows
+        Unknown(u32),
+    }
+
+    impl EndFileReason {
+        fn from_raw(reason: sys::mpv_end_file_reason) -> Self {
+            match reason {
+                sys::mpv_end_file_reason_MPV_END_FILE_REASON_EOF => EndFileReason::Eof,
+                sys::mpv_end_file_reason_MPV_END_FILE_REASON_STOP => EndFileReason::Stop,
+                sys::mpv_end_file_reason_MPV_END_FILE_REASON_QUIT => EndFileReason::Quit,
+                sys::mpv_end_file_reason_MPV_END_FILE_REASON_ERROR => EndFileReason::Error,
+                sys::mpv_end_file_reason_MPV_END_FILE_REASON_REDIRECT => EndFileReason::Redirect,
+                reason => EndFileReason::Unknown(reason),
+            }
+        }
+    }
+
     pub(super) fn convert_event(e: *mut sys::mpv_event) -> Option<MpvEvent> {
         debug_assert!(!e.is_null());
         unsafe {
             if (*e).event_id == sys::mpv_event_id_MPV_EVENT_NONE {
                 None
+            } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_SHUTDOWN {
+                Some(MpvEvent::Shutdown)
             } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_PROPERTY_CHANGE {
                 let prop = (*e).data as *const sys::mpv_event_property;
                 match Property::from_raw(prop) {
-                    Ok(p) => Some(MpvEvent::PropertyChange(p)),
+                    Ok(property) => Some(MpvEvent::PropertyChange {
+                        id: (*e).reply_userdata,
+                        property,
+                    }),
                     Err(_) => Some(MpvEvent::Error),
                 }
             } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_START_FILE {
@@ -409,6 +1006,37 @@ pub mod event {
                 Some(MpvEvent::StartFile {
                     playlist_entry_id: (*prop).playlist_entry_id,
                 })
+            } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_CLIENT_MESSAGE {
+                let msg = (*e).data as *const sys::mpv_event_client_message;
+                debug_assert!(!msg.is_null());
+                let num = (*msg).num_args.max(0) as usize;
+                let args = if num == 0 {
+                    &[]
+                } else {
+                    std::slice::from_raw_parts((*msg).args, num)
+                };
+                let args = args.iter().map(|arg| {
+                    let arg = std::ffi::CStr::from_ptr(*arg);
+                    arg.to_string_lossy().into_owned()
+                });
+                Some(MpvEvent::ClientMessage {
+                    args: args.collect(),
+                })
+            } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_LOG_MESSAGE {
+                let msg = (*e).data as *const sys::mpv_event_log_message;
+                Some(MpvEvent::LogMessage(super::logging::LogMessage::from_raw(
+                    msg,
+                )))
+            } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_END_FILE {
+                let prop = (*e).data as *const sys::mpv_event_end_file;
+                debug_assert!(!prop.is_null());
+                Some(MpvEvent::EndFile {
+                    reason: EndFileReason::from_raw((*prop).reason),
+                    error: super::ErrorKind::from_code((*prop).error),
+                    playlist_entry_id: (*prop).playlist_entry_id,
+                    playlist_insert_id: (*prop).playlist_insert_id,
+                    playlist_insert_num_entries: (*prop).playlist_insert_num_entries,
+                })
             } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_FILE_LOADED {
                 Some(MpvEvent::FileLoaded)
             } else if (*e).event_id == sys::mpv_event_id_MPV_EVENT_PLAYBACK_RESTART {
@@ -424,81 +1052,223 @@ pub mod event {
     }
 }
 
-#[allow(dead_code)]
 impl Mpv {
-    pub fn wait_event(&self, timeout: f64) -> Option<event::MpvEvent> {
-        let event_ptr = unsafe { sys::mpv_wait_event(self.ptr, timeout) };
-        event::convert_event(event_ptr)
+    /// Start sending [`MpvEvent::LogMessage`](event::MpvEvent::LogMessage)
+    /// of `level` and above. [`LogLevel::None`](logging::LogLevel::None)
+    /// stops them.
+    pub fn request_log_messages(&self, level: logging::LogLevel) -> Result<()> {
+        let e = unsafe { sys::mpv_request_log_messages(self.ptr, level.as_c_str().as_ptr()) };
+        Error::raise(e).map_err(|e| e.context("request_log_messages", None))
+    }
+
+    /// Run `handler` every time mpv reaches hook `name`, like `on_load`,
+    /// `on_preloaded` or `on_unload`. Mpv waits for the handler to return,
+    /// and continues even if it panics, in which case the panic is logged
+    /// and goes no further. Handlers with lower `priority` run first. Hooks
+    /// can't be removed, and run on whatever thread reads events.
+    pub fn add_hook<F>(&self, name: &str, priority: i32, handler: F) -> Result<()>
+    where
+        F: FnMut(&hooks::Hook<'_>) + Send + 'static,
+    {
+        let context = |e: Error| e.context("hook_add", Some(name));
+        let c_name = std::ffi::CString::new(name).map_err(|e| context(e.into()))?;
+        let id = self.next_id();
+        // registered first, as the hook can fire as soon as it's added
+        self.hooks.insert(id, Box::new(handler));
+        let e = unsafe { sys::mpv_hook_add(self.ptr, id, c_name.as_ptr(), priority) };
+        Error::raise(e).map_err(|e| {
+            // mpv won't ever call it
+            self.hooks.remove(id);
+            context(e)
+        })
+    }
+
+    /// Open urls starting with `protocol://` through `provider`. A protocol
+    /// can't be registered twice, or replaced later.
+    ///
+    /// The protocol belongs to the whole player and outlives any client, so
+    /// this only works on the handle made by [`MpvBuilder`], and fails with
+    /// [`ErrorKind::InvalidParameter`] on handles from
+    /// [`create_client`](Self::create_client).
+    pub fn add_stream_protocol<P: stream::StreamProvider>(
+        &self,
+        protocol: &str,
+        provider: P,
+    ) -> Result<()> {
+        let context = |e: Error| e.context("stream_cb_add_ro", Some(protocol));
+        if !self.terminate {
+            return Err(context(ErrorKind::InvalidParameter.into()));
+        }
+        let c_protocol = std::ffi::CString::new(protocol).map_err(|e| context(e.into()))?;
+        let provider = Box::new(provider);
+        let provider_ptr = &*provider as *const P as *mut c_void;
+        // Safety: the provider is dropped after mpv_terminate_destroy, when
+        // the player and all its clients are gone
+        let e = unsafe {
+            sys::mpv_stream_cb_add_ro(
+                self.ptr,
+                c_protocol.as_ptr(),
+                provider_ptr,
+                Some(stream::open::<P>),
+            )
+        };
+        Error::raise(e).map_err(context)?;
+        self.stream_providers.lock().unwrap().push(provider);
+        Ok(())
+    }
+
+    /// Send `script-message` with these arguments to every script and
+    /// client. The first one is the message name by convention.
+    pub fn send_script_message<I, S>(&self, args: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let args = args.into_iter().map(Into::into).collect();
+        self.command(&command::Command::ScriptMessage(args))
+    }
+
+    /// Interrupt [`Events::wait_event`](event::Events::wait_event) and call
+    /// the wakeup callback
+    pub fn wakeup(&self) {
+        unsafe { sys::mpv_wakeup(self.ptr) }
     }
 
-    /// Notify mpv that we want to observe property events
-    pub fn observe_property<P: property::ReadProperty>(&self) -> Result<()> {
+    /// Notify mpv that we want to observe property events. Changes come as
+    /// [`MpvEvent::PropertyChange`](event::MpvEvent::PropertyChange) with
+    /// the id of the returned subscription, until it's dropped.
+    pub fn observe_property<P: property::ReadProperty>(
+        self: &std::sync::Arc<Self>,
+    ) -> Result<Subscription> {
+        let id = self.next_id();
         unsafe {
-            let e = sys::mpv_observe_property(self.ptr, 0, P::NAME.as_ptr(), P::FORMAT);
-            Error::raise(e)
+            let e = sys::mpv_observe_property(self.ptr, id, P::NAME.as_ptr(), P::FORMAT);
+            Error::check(e, "observe_property", P::NAME)?;
         }
+        Ok(Subscription {
+            mpv: self.clone(),
+            id,
+        })
     }
 
     pub fn get_property<P: property::ReadProperty>(&self) -> Result<P> {
+        use property::Repr;
         let mut buffer = P::MpvRepr::default();
         let e = unsafe {
             let ptr = &mut buffer as *mut P::MpvRepr;
             sys::mpv_get_property(self.ptr, P::NAME.as_ptr(), P::FORMAT, ptr as *mut c_void)
         };
-        Error::raises(P::from_repr(buffer), e)
+        Error::check(e, "get_property", P::NAME)?;
+        let value = P::from_repr(buffer);
+        // Safety: mpv has filled the buffer, and we've copied everything out
+        unsafe { buffer.free_from_mpv() };
+        Ok(value)
+    }
+
+    pub fn get_property_async<P>(&self) -> reply::ReplyFuture<'_, P>
+    where
+        P: property::ReadProperty + Send + 'static,
+    {
+        let id = self.next_id();
+        let future = reply::ReplyFuture::new(self, id, reply::Kind::Property, |e| {
+            Error::check(e.error, "get_property", P::NAME)?;
+            let prop = e.data as *const sys::mpv_event_property;
+            debug_assert!(!prop.is_null());
+            // Safety: property replies always have this data, owned by the
+            // event, so not freed here
+            unsafe {
+                if (*prop).format != P::FORMAT {
+                    let e = Error::from(property::ConvertError::TypeError);
+                    return Err(e.context("get_property", Some(&P::NAME.to_string_lossy())));
+                }
+                let data = (*prop).data as *const P::MpvRepr;
+                Ok(P::from_repr(*data))
+            }
+        });
+        let e = unsafe { sys::mpv_get_property_async(self.ptr, id, P::NAME.as_ptr(), P::FORMAT) };
+        match Error::check(e, "get_property", P::NAME) {
+            Ok(()) => future,
+            Err(e) => future.fail(e),
+        }
+    }
+
+    pub fn set_property_async<P: property::WriteProperty>(
+        &self,
+        p: &P,
+    ) -> reply::ReplyFuture<'_, ()> {
+        use property::Repr;
+        let id = self.next_id();
+        let future = reply::ReplyFuture::new(self, id, reply::Kind::Property, |e| {
+            Error::check(e.error, "set_property", P::NAME)
+        });
+        let data = match p.to_repr() {
+            Ok(data) => data,
+            Err(e) => {
+                return future.fail(e.context("set_property", Some(&P::NAME.to_string_lossy())))
+            }
+        };
+        let data_ptr = &data as *const P::MpvRepr as *mut c_void;
+        // Safety: same as set_property, mpv copies the data immediately
+        let e = unsafe {
+            let e =
+                sys::mpv_set_property_async(self.ptr, id, P::NAME.as_ptr(), P::FORMAT, data_ptr);
+            data.free_from_rust();
+            e
+        };
+        match Error::check(e, "set_property", P::NAME) {
+            Ok(()) => future,
+            Err(e) => future.fail(e),
+        }
     }
 
     pub fn set_property<P: property::WriteProperty>(&self, p: &P) -> Result<()> {
-        let data = p.to_repr();
+        use property::Repr;
+        let data = p
+            .to_repr()
+            .map_err(|e| e.context("set_property", Some(&P::NAME.to_string_lossy())))?;
         let data_ptr = &data as *const P::MpvRepr;
         let data_ptr = data_ptr as *mut c_void;
         // Safety: safe as ptr is valid, and data from property is also valid
         let e = unsafe { sys::mpv_set_property(self.ptr, P::NAME.as_ptr(), P::FORMAT, data_ptr) };
-        Error::raise(e)
+        // Safety: mpv copies the data, so we can free it right away
+        unsafe { data.free_from_rust() };
+        Error::check(e, "set_property", P::NAME)
     }
 }
 
-/// Safety: `closure_ptr` must be Box<F>
-unsafe extern "C" fn call_closure_0<F>(closure_ptr: *mut c_void)
-where
-    F: FnMut(),
-{
-    let closure_ptr = closure_ptr as *mut F;
-    let mut closure: Box<F> = Box::from_raw(closure_ptr);
-    closure();
-    Box::leak(closure);
+/// Observation of a property, stops when dropped
+pub struct Subscription {
+    mpv: std::sync::Arc<Mpv>,
+    id: u64,
 }
 
-pub struct Error(i32);
-
-// panic uses Debug for showing error, not Display? Fucking why?
-impl std::fmt::Debug for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // Safety: perfectly fine function
-        let desc = unsafe { sys::mpv_error_string(self.0) };
-        // Safety: guaranteed c string
-        let desc = unsafe { std::ffi::CStr::from_ptr(desc) };
-        let desc = desc.to_string_lossy();
-        write!(f, "\"{}\"", desc)
+impl Subscription {
+    /// Matches the id in property change events
+    pub fn id(&self) -> u64 {
+        self.id
     }
 }
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Debug::fmt(self, f)
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Safety: the id can't be observed by anyone else. Changes that are
+        // already queued will still be delivered.
+        unsafe { sys::mpv_unobserve_property(self.mpv.ptr, self.id) };
     }
 }
 
-impl std::error::Error for Error {}
-
-impl Error {
-    fn raise(e: i32) -> Result<()> {
-        Self::raises((), e)
-    }
-    fn raises<T>(x: T, e: i32) -> Result<T> {
-        if e != sys::mpv_error_MPV_ERROR_SUCCESS {
-            Err(Error(e))
-        } else {
-            Ok(x)
-        }
+/// Safety: `slot_ptr` must be a live CallbackSlot
+unsafe extern "C" fn call_slot(slot_ptr: *mut c_void) {
+    let slot = &*(slot_ptr as *const CallbackSlot);
+    if let Some(cb) = lock(slot).as_ref() {
+        // can't unwind into mpv
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(cb));
     }
 }
+
+/// Callbacks from mpv threads can't propagate panics, so a poisoned slot is
+/// still used
+fn lock(slot: &CallbackSlot) -> std::sync::MutexGuard<'_, Option<Callback>> {
+    slot.lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
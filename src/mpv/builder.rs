@@ -0,0 +1,120 @@
+//! Everything that has to happen before `mpv_initialize`. An [`Mpv`] only
+//! exists initialized, so options can't be set on it by mistake afterwards.
+
+use std::ffi::{c_void, CString};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::command::Command;
+use super::event::Events;
+use super::property::{Repr, Value, WriteProperty};
+use super::{sys, Error, ErrorKind, Mpv, Result};
+
+pub struct MpvBuilder {
+    mpv: Mpv,
+    /// Applied after initialization, when config files are loaded
+    profiles: Vec<String>,
+}
+
+impl MpvBuilder {
+    pub fn new() -> Result<Self> {
+        // mpv doesn't say why it failed, it's not only out of memory but also
+        // things like a wrong locale
+        let mpv =
+            Mpv::new().ok_or_else(|| Error::new(ErrorKind::Generic).context("create", None))?;
+        Ok(Self {
+            mpv,
+            profiles: vec![],
+        })
+    }
+
+    /// Set an option in its native format, see
+    /// https://mpv.io/manual/master/#options
+    pub fn option<V: Value>(self, name: &str, value: V) -> Result<Self> {
+        let context = |e: Error| e.context("set_option", Some(name));
+        let c_name = CString::new(name).map_err(|e| context(e.into()))?;
+        let data = value.to_repr().map_err(context)?;
+        let data_ptr = &data as *const V::MpvRepr as *mut c_void;
+        // Safety: mpv copies the data, so we can free it right away
+        let e = unsafe {
+            let e = sys::mpv_set_option(self.mpv.ptr, c_name.as_ptr(), V::FORMAT, data_ptr);
+            data.free_from_rust();
+            e
+        };
+        Error::raise(e).map_err(context)?;
+        Ok(self)
+    }
+
+    /// Options that are also properties can be set with the property type
+    pub fn property<P: WriteProperty>(self, p: &P) -> Result<Self> {
+        let data = p
+            .to_repr()
+            .map_err(|e| e.context("set_option", Some(&P::NAME.to_string_lossy())))?;
+        let data_ptr = &data as *const P::MpvRepr as *mut c_void;
+        // Safety: same as in option
+        let e = unsafe {
+            let e = sys::mpv_set_option(self.mpv.ptr, P::NAME.as_ptr(), P::FORMAT, data_ptr);
+            data.free_from_rust();
+            e
+        };
+        Error::check(e, "set_option", P::NAME)?;
+        Ok(self)
+    }
+
+    /// Set an option the way it's written on the command line
+    pub fn option_string(self, name: &str, value: &str) -> Result<Self> {
+        self.set_option_bytes(name, value.as_bytes())?;
+        Ok(self)
+    }
+
+    /// Load options from a file in `mpv.conf` format right away. Profiles
+    /// defined there can be selected with [`profile`](Self::profile).
+    pub fn config_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let context = |e: Error| e.context("load_config_file", Some(&path.to_string_lossy()));
+        let c_path = path_to_c_string(path).map_err(|e| context(e.into()))?;
+        let e = unsafe { sys::mpv_load_config_file(self.mpv.ptr, c_path.as_ptr()) };
+        Error::raise(e).map_err(context)?;
+        Ok(self)
+    }
+
+    /// Load `mpv.conf`, scripts and the rest from `dir` during
+    /// initialization, like the mpv player does from `~/.config/mpv`.
+    /// Libmpv doesn't load any config by default.
+    pub fn config_dir(self, dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().as_os_str().as_encoded_bytes();
+        self.set_option_bytes("config-dir", dir)?;
+        self.option("config", true)
+    }
+
+    /// Apply a profile after initialization, so it can come from the config
+    /// directory. Profiles are applied in the order given.
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.profiles.push(name.into());
+        self
+    }
+
+    /// The handle, together with its only event queue
+    pub fn build(self) -> Result<(Arc<Mpv>, Events)> {
+        self.mpv.initialize()?;
+        for profile in self.profiles {
+            let cmd = Command::Raw(vec!["apply-profile".to_owned(), profile]);
+            self.mpv.command(&cmd)?;
+        }
+        Ok(self.mpv.with_events())
+    }
+
+    fn set_option_bytes(&self, name: &str, value: &[u8]) -> Result<()> {
+        let context = |e: Error| e.context("set_option", Some(name));
+        let c_name = CString::new(name).map_err(|e| context(e.into()))?;
+        let c_value = CString::new(value).map_err(|e| context(e.into()))?;
+        // Safety: it's just cstrings
+        let e =
+            unsafe { sys::mpv_set_option_string(self.mpv.ptr, c_name.as_ptr(), c_value.as_ptr()) };
+        Error::raise(e).map_err(context)
+    }
+}
+
+fn path_to_c_string(path: &Path) -> std::result::Result<CString, std::ffi::NulError> {
+    CString::new(path.as_os_str().as_encoded_bytes())
+}
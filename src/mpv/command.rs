@@ -0,0 +1,680 @@
+//! Well-typed mpv commands. They are sent with named arguments through
+//! `mpv_command_node`, so we don't depend on positional argument order, which
+//! changes between mpv versions.
+//!
+//! See https://mpv.io/manual/master/#list-of-input-commands
+
+use std::ffi::{OsStr, OsString};
+
+use super::node::MpvNode;
+use super::property::{ConvertError, ReadProperty};
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    LoadFile(LoadFile),
+    Seek(Seek),
+    Stop {
+        keep_playlist: bool,
+    },
+    Quit {
+        code: Option<i32>,
+    },
+    /// `force` goes to next file even if this is the last one, and stops
+    /// playback then
+    PlaylistNext {
+        force: bool,
+    },
+    PlaylistPrev {
+        force: bool,
+    },
+    PlaylistPlayIndex(i64),
+    PlaylistRemove(i64),
+    PlaylistClear,
+    PlaylistShuffle,
+    FrameStep,
+    FrameBackStep,
+    SubAdd(SubAdd),
+    SubRemove {
+        id: Option<i64>,
+    },
+    SubReload {
+        id: Option<i64>,
+    },
+    Screenshot(ScreenshotMode),
+    ScreenshotToFile {
+        filename: String,
+        mode: ScreenshotMode,
+    },
+    Cycle {
+        property: String,
+        up: bool,
+    },
+    Add {
+        property: String,
+        value: f64,
+    },
+    Multiply {
+        property: String,
+        value: f64,
+    },
+    Set {
+        property: String,
+        value: String,
+    },
+    ShowText {
+        text: String,
+        duration_ms: Option<i64>,
+    },
+    ScriptMessage(Vec<String>),
+    ExpandPath(ExpandPath),
+    Subprocess(Subprocess),
+    ScreenshotRaw(ScreenshotRaw),
+    /// Escape hatch for commands not covered here, with positional string
+    /// arguments, the first being the command name
+    Raw(Vec<String>),
+}
+
+impl Command {
+    /// Paths don't need to be valid UTF-8
+    pub fn load_file(url: impl AsRef<OsStr>) -> Self {
+        Command::LoadFile(LoadFile::new(url))
+    }
+
+    pub fn cycle<P: ReadProperty>(up: bool) -> Self {
+        Command::Cycle {
+            property: property_name::<P>(),
+            up,
+        }
+    }
+
+    pub fn add<P: ReadProperty>(value: f64) -> Self {
+        Command::Add {
+            property: property_name::<P>(),
+            value,
+        }
+    }
+
+    pub fn multiply<P: ReadProperty>(value: f64) -> Self {
+        Command::Multiply {
+            property: property_name::<P>(),
+            value,
+        }
+    }
+
+    pub fn set<P: ReadProperty>(value: impl Into<String>) -> Self {
+        Command::Set {
+            property: property_name::<P>(),
+            value: value.into(),
+        }
+    }
+
+    /// Name of the command as mpv knows it, for error messages
+    pub fn name(&self) -> &str {
+        match self {
+            Command::LoadFile(_) => "loadfile",
+            Command::Seek(_) => "seek",
+            Command::Stop { .. } => "stop",
+            Command::Quit { .. } => "quit",
+            Command::PlaylistNext { .. } => "playlist-next",
+            Command::PlaylistPrev { .. } => "playlist-prev",
+            Command::PlaylistPlayIndex(_) => "playlist-play-index",
+            Command::PlaylistRemove(_) => "playlist-remove",
+            Command::PlaylistClear => "playlist-clear",
+            Command::PlaylistShuffle => "playlist-shuffle",
+            Command::FrameStep => "frame-step",
+            Command::FrameBackStep => "frame-back-step",
+            Command::SubAdd(_) => "sub-add",
+            Command::SubRemove { .. } => "sub-remove",
+            Command::SubReload { .. } => "sub-reload",
+            Command::Screenshot(_) => "screenshot",
+            Command::ScreenshotToFile { .. } => "screenshot-to-file",
+            Command::Cycle { .. } => "cycle",
+            Command::Add { .. } => "add",
+            Command::Multiply { .. } => "multiply",
+            Command::Set { .. } => "set",
+            Command::ShowText { .. } => "show-text",
+            Command::ScriptMessage(_) => "script-message",
+            Command::ExpandPath(_) => "expand-path",
+            Command::Subprocess(_) => "subprocess",
+            Command::ScreenshotRaw(_) => "screenshot-raw",
+            Command::Raw(args) => args.first().map_or("", String::as_str),
+        }
+    }
+
+    /// Node as expected by `mpv_command_node`
+    pub fn to_node(&self) -> MpvNode {
+        let name = self.name();
+        let named = |args: Vec<(&str, MpvNode)>| {
+            let name = ("name".to_owned(), MpvNode::from(name));
+            let args = args.into_iter().map(|(k, v)| (k.to_owned(), v));
+            MpvNode::Map(std::iter::once(name).chain(args).collect())
+        };
+        let positional = |args: &[String]| {
+            MpvNode::Array(args.iter().map(|s| MpvNode::from(s.as_str())).collect())
+        };
+        match self {
+            Command::LoadFile(x) => {
+                let mut args = vec![
+                    ("url", x.url.as_os_str().into()),
+                    ("flags", x.mode.flag().into()),
+                ];
+                if let Some(index) = x.mode.index() {
+                    args.push(("index", index.into()));
+                }
+                if !x.options.is_empty() {
+                    args.push(("options", escape_key_value_list(&x.options).into()));
+                }
+                named(args)
+            }
+            Command::Seek(x) => named(vec![
+                ("target", x.target.into()),
+                ("flags", x.flags().into()),
+            ]),
+            Command::Stop { keep_playlist } => {
+                let mut args = vec![];
+                if *keep_playlist {
+                    args.push(("flags", "keep-playlist".into()));
+                }
+                named(args)
+            }
+            Command::Quit { code } => {
+                let args = code.map(|c| ("code", i64::from(c).into()));
+                named(args.into_iter().collect())
+            }
+            Command::PlaylistNext { force } => named(vec![("flags", force_flag(*force).into())]),
+            Command::PlaylistPrev { force } => named(vec![("flags", force_flag(*force).into())]),
+            Command::PlaylistPlayIndex(i) => named(vec![("index", i.to_string().into())]),
+            Command::PlaylistRemove(i) => named(vec![("index", i.to_string().into())]),
+            Command::PlaylistClear => named(vec![]),
+            Command::PlaylistShuffle => named(vec![]),
+            Command::FrameStep => named(vec![]),
+            Command::FrameBackStep => named(vec![]),
+            Command::SubAdd(x) => {
+                let mut args = vec![
+                    ("url", x.url.as_os_str().into()),
+                    ("flags", x.flag.flag().into()),
+                ];
+                if let Some(title) = &x.title {
+                    args.push(("title", title.as_str().into()));
+                }
+                if let Some(lang) = &x.lang {
+                    args.push(("lang", lang.as_str().into()));
+                }
+                named(args)
+            }
+            Command::SubRemove { id } => {
+                let args = id.map(|id| ("id", id.into()));
+                named(args.into_iter().collect())
+            }
+            Command::SubReload { id } => {
+                let args = id.map(|id| ("id", id.into()));
+                named(args.into_iter().collect())
+            }
+            Command::Screenshot(mode) => named(vec![("flags", mode.flag().into())]),
+            Command::ScreenshotToFile { filename, mode } => named(vec![
+                ("filename", filename.as_str().into()),
+                ("flags", mode.flag().into()),
+            ]),
+            // "name" is both the command name and the argument name for these
+            // ones, so they have to be positional
+            Command::Cycle { property, up } => MpvNode::Array(vec![
+                name.into(),
+                property.as_str().into(),
+                if *up { "up" } else { "down" }.into(),
+            ]),
+            Command::Add { property, value } => {
+                MpvNode::Array(vec![name.into(), property.as_str().into(), (*value).into()])
+            }
+            Command::Multiply { property, value } => {
+                MpvNode::Array(vec![name.into(), property.as_str().into(), (*value).into()])
+            }
+            Command::Set { property, value } => MpvNode::Array(vec![
+                name.into(),
+                property.as_str().into(),
+                value.as_str().into(),
+            ]),
+            Command::ShowText { text, duration_ms } => {
+                let mut args = vec![("text", text.as_str().into())];
+                if let Some(d) = duration_ms {
+                    args.push(("duration", (*d).into()));
+                }
+                named(args)
+            }
+            Command::ScriptMessage(args) => {
+                let mut node = positional(args);
+                if let MpvNode::Array(xs) = &mut node {
+                    xs.insert(0, name.into());
+                }
+                node
+            }
+            Command::ExpandPath(x) => named(vec![("text", x.0.as_str().into())]),
+            Command::Subprocess(x) => {
+                let args = x.args.iter().map(|s| s.as_str().into()).collect();
+                let mut args = vec![
+                    ("args", MpvNode::Array(args)),
+                    ("playback_only", x.playback_only.into()),
+                    ("capture_stdout", x.capture_stdout.into()),
+                    ("capture_stderr", x.capture_stderr.into()),
+                    ("detach", x.detach.into()),
+                ];
+                if let Some(stdin) = &x.stdin_data {
+                    args.push(("stdin_data", stdin.as_str().into()));
+                }
+                named(args)
+            }
+            Command::ScreenshotRaw(x) => named(vec![("flags", x.0.flag().into())]),
+            Command::Raw(args) => positional(args),
+        }
+    }
+}
+
+/// Command which returns a result, decoded into a rust type. Run it with
+/// [`Mpv::command_typed`](super::Mpv::command_typed).
+pub trait TypedCommand {
+    type Output;
+    fn command(&self) -> Command;
+    fn decode(result: MpvNode) -> Result<Self::Output, ConvertError>;
+}
+
+/// Open a file or url. Build with [`LoadFile::new`] and chain the setters.
+#[derive(Debug, Clone)]
+pub struct LoadFile {
+    /// Url or path, which doesn't need to be valid UTF-8
+    pub url: OsString,
+    pub mode: LoadFileMode,
+    /// Per-file options, like with `--start` or `--sub-file` on command line
+    pub options: Vec<(String, String)>,
+}
+
+impl LoadFile {
+    pub fn new(url: impl AsRef<OsStr>) -> Self {
+        Self {
+            url: url.as_ref().to_owned(),
+            mode: LoadFileMode::Replace,
+            options: Vec::new(),
+        }
+    }
+
+    pub fn mode(mut self, mode: LoadFileMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl TypedCommand for LoadFile {
+    type Output = LoadFileResult;
+    fn command(&self) -> Command {
+        Command::LoadFile(self.clone())
+    }
+    fn decode(result: MpvNode) -> Result<Self::Output, ConvertError> {
+        Ok(LoadFileResult {
+            playlist_entry_id: result.get("playlist_entry_id").and_then(MpvNode::as_i64),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LoadFileResult {
+    /// Only returned by mpv 0.35 and newer
+    pub playlist_entry_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadFileMode {
+    Replace,
+    Append,
+    AppendPlay,
+    InsertNext,
+    InsertNextPlay,
+    /// Requires mpv 0.38 or newer
+    InsertAt(i64),
+    /// Requires mpv 0.38 or newer
+    InsertAtPlay(i64),
+}
+
+impl LoadFileMode {
+    fn flag(self) -> &'static str {
+        match self {
+            LoadFileMode::Replace => "replace",
+            LoadFileMode::Append => "append",
+            LoadFileMode::AppendPlay => "append-play",
+            LoadFileMode::InsertNext => "insert-next",
+            LoadFileMode::InsertNextPlay => "insert-next-play",
+            LoadFileMode::InsertAt(_) => "insert-at",
+            LoadFileMode::InsertAtPlay(_) => "insert-at-play",
+        }
+    }
+
+    fn index(self) -> Option<i64> {
+        match self {
+            LoadFileMode::InsertAt(i) | LoadFileMode::InsertAtPlay(i) => Some(i),
+            _ => None,
+        }
+    }
+}
+
+/// Build with one of the constructors, and optionally set precision
+#[derive(Debug, Clone, Copy)]
+pub struct Seek {
+    pub target: f64,
+    pub mode: SeekMode,
+    pub precision: SeekPrecision,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    Relative,
+    Absolute,
+    RelativePercent,
+    AbsolutePercent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekPrecision {
+    /// Whatever `--hr-seek` says
+    Default,
+    Exact,
+    Keyframes,
+}
+
+impl Seek {
+    fn new(target: f64, mode: SeekMode) -> Self {
+        Self {
+            target,
+            mode,
+            precision: SeekPrecision::Default,
+        }
+    }
+
+    /// Seconds from current position, can be negative
+    pub fn relative(seconds: f64) -> Self {
+        Self::new(seconds, SeekMode::Relative)
+    }
+    pub fn absolute(seconds: f64) -> Self {
+        Self::new(seconds, SeekMode::Absolute)
+    }
+    pub fn relative_percent(percent: f64) -> Self {
+        Self::new(percent, SeekMode::RelativePercent)
+    }
+    pub fn absolute_percent(percent: f64) -> Self {
+        Self::new(percent, SeekMode::AbsolutePercent)
+    }
+
+    pub fn exact(mut self) -> Self {
+        self.precision = SeekPrecision::Exact;
+        self
+    }
+    pub fn keyframes(mut self) -> Self {
+        self.precision = SeekPrecision::Keyframes;
+        self
+    }
+
+    fn flags(&self) -> String {
+        let mode = match self.mode {
+            SeekMode::Relative => "relative",
+            SeekMode::Absolute => "absolute",
+            SeekMode::RelativePercent => "relative-percent",
+            SeekMode::AbsolutePercent => "absolute-percent",
+        };
+        match self.precision {
+            SeekPrecision::Default => mode.to_owned(),
+            SeekPrecision::Exact => format!("{}+exact", mode),
+            SeekPrecision::Keyframes => format!("{}+keyframes", mode),
+        }
+    }
+}
+
+/// Add an external subtitle file
+#[derive(Debug, Clone)]
+pub struct SubAdd {
+    /// Url or path, which doesn't need to be valid UTF-8
+    pub url: OsString,
+    pub flag: SubAddFlag,
+    pub title: Option<String>,
+    pub lang: Option<String>,
+}
+
+impl SubAdd {
+    pub fn new(url: impl AsRef<OsStr>) -> Self {
+        Self {
+            url: url.as_ref().to_owned(),
+            flag: SubAddFlag::Select,
+            title: None,
+            lang: None,
+        }
+    }
+
+    pub fn flag(mut self, flag: SubAddFlag) -> Self {
+        self.flag = flag;
+        self
+    }
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubAddFlag {
+    /// Select the subtitle immediately
+    Select,
+    /// Don't select it
+    Auto,
+    /// Select an already added track with the same url if there is one
+    Cached,
+}
+
+impl SubAddFlag {
+    fn flag(self) -> &'static str {
+        match self {
+            SubAddFlag::Select => "select",
+            SubAddFlag::Auto => "auto",
+            SubAddFlag::Cached => "cached",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotMode {
+    /// Video with subtitles
+    Subtitles,
+    /// Only video
+    Video,
+    /// Everything mpv has drawn, including OSD
+    Window,
+}
+
+impl ScreenshotMode {
+    fn flag(self) -> &'static str {
+        match self {
+            ScreenshotMode::Subtitles => "subtitles",
+            ScreenshotMode::Video => "video",
+            ScreenshotMode::Window => "window",
+        }
+    }
+}
+
+/// Expand a path with mpv's meta paths like `~~/` or `~/`
+#[derive(Debug, Clone)]
+pub struct ExpandPath(pub String);
+
+impl TypedCommand for ExpandPath {
+    type Output = String;
+    fn command(&self) -> Command {
+        Command::ExpandPath(self.clone())
+    }
+    fn decode(result: MpvNode) -> Result<Self::Output, ConvertError> {
+        match result {
+            MpvNode::String(s) => Ok(s.to_string_lossy().into_owned()),
+            _ => Err(ConvertError::TypeError),
+        }
+    }
+}
+
+/// Run an external process. Build with [`Subprocess::new`].
+#[derive(Debug, Clone)]
+pub struct Subprocess {
+    pub args: Vec<String>,
+    /// Kill the process when playback of the current file stops
+    pub playback_only: bool,
+    pub capture_stdout: bool,
+    pub capture_stderr: bool,
+    /// Don't wait for the process, and return nothing useful
+    pub detach: bool,
+    pub stdin_data: Option<String>,
+}
+
+impl Subprocess {
+    pub fn new(args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            args: args.into_iter().map(Into::into).collect(),
+            playback_only: false,
+            capture_stdout: true,
+            capture_stderr: false,
+            detach: false,
+            stdin_data: None,
+        }
+    }
+
+    pub fn playback_only(mut self, x: bool) -> Self {
+        self.playback_only = x;
+        self
+    }
+    pub fn capture_stdout(mut self, x: bool) -> Self {
+        self.capture_stdout = x;
+        self
+    }
+    pub fn capture_stderr(mut self, x: bool) -> Self {
+        self.capture_stderr = x;
+        self
+    }
+    pub fn detach(mut self, x: bool) -> Self {
+        self.detach = x;
+        self
+    }
+    pub fn stdin_data(mut self, data: impl Into<String>) -> Self {
+        self.stdin_data = Some(data.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SubprocessResult {
+    /// Exit code, or negative on errors like failing to start
+    pub status: i64,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Empty on success, `"killed"` or `"init"` otherwise
+    pub error_string: String,
+    pub killed_by_us: bool,
+}
+
+impl TypedCommand for Subprocess {
+    type Output = SubprocessResult;
+    fn command(&self) -> Command {
+        Command::Subprocess(self.clone())
+    }
+    fn decode(result: MpvNode) -> Result<Self::Output, ConvertError> {
+        let bytes = |key| {
+            result
+                .get(key)
+                .and_then(MpvNode::as_bytes)
+                .map(<[u8]>::to_vec)
+                .unwrap_or_default()
+        };
+        Ok(SubprocessResult {
+            status: result
+                .get("status")
+                .and_then(MpvNode::as_i64)
+                .ok_or(ConvertError::TypeError)?,
+            stdout: bytes("stdout"),
+            stderr: bytes("stderr"),
+            error_string: result
+                .get("error_string")
+                .and_then(MpvNode::as_str)
+                .unwrap_or_default()
+                .to_owned(),
+            killed_by_us: result
+                .get("killed_by_us")
+                .and_then(MpvNode::as_bool)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Take a screenshot and return it instead of writing to a file
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenshotRaw(pub ScreenshotMode);
+
+/// Raw image as returned by `screenshot-raw`
+#[derive(Debug, Clone)]
+pub struct Screenshot {
+    pub width: i64,
+    pub height: i64,
+    /// Bytes per row, might be more than `width * 4`
+    pub stride: i64,
+    /// Always `"bgr0"` for now
+    pub format: String,
+    pub data: Vec<u8>,
+}
+
+impl TypedCommand for ScreenshotRaw {
+    type Output = Screenshot;
+    fn command(&self) -> Command {
+        Command::ScreenshotRaw(*self)
+    }
+    fn decode(result: MpvNode) -> Result<Self::Output, ConvertError> {
+        let int = |key| {
+            result
+                .get(key)
+                .and_then(MpvNode::as_i64)
+                .ok_or(ConvertError::TypeError)
+        };
+        Ok(Screenshot {
+            width: int("w")?,
+            height: int("h")?,
+            stride: int("stride")?,
+            format: result
+                .get("format")
+                .and_then(MpvNode::as_str)
+                .ok_or(ConvertError::TypeError)?
+                .to_owned(),
+            data: result
+                .get("data")
+                .and_then(MpvNode::as_bytes)
+                .ok_or(ConvertError::TypeError)?
+                .to_vec(),
+        })
+    }
+}
+
+fn force_flag(force: bool) -> &'static str {
+    if force {
+        "force"
+    } else {
+        "weak"
+    }
+}
+
+fn property_name<P: ReadProperty>() -> String {
+    P::NAME.to_string_lossy().into_owned()
+}
+
+/// Key-value lists are split on `,` and `=`, but mpv lets values be prefixed
+/// with their byte length as `%len%` to pass them verbatim
+fn escape_key_value_list(options: &[(String, String)]) -> String {
+    options
+        .iter()
+        .map(|(k, v)| format!("{}=%{}%{}", k, v.len(), v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
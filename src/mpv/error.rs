@@ -0,0 +1,214 @@
+//! Errors from mpv calls, with what we were doing when they happened
+
+use std::ffi::{c_int, CStr};
+
+use super::property::ConvertError;
+use super::sys;
+
+/// Every `mpv_error` code, plus problems found on the rust side before or
+/// after calling mpv
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    EventQueueFull,
+    NoMem,
+    Uninitialized,
+    InvalidParameter,
+    OptionNotFound,
+    OptionFormat,
+    OptionError,
+    PropertyNotFound,
+    PropertyFormat,
+    /// Property exists, but has no value right now, like volume before audio
+    /// output is initialized
+    PropertyUnavailable,
+    PropertyError,
+    Command,
+    LoadingFailed,
+    AoInitFailed,
+    VoInitFailed,
+    NothingToPlay,
+    UnknownFormat,
+    Unsupported,
+    NotImplemented,
+    Generic,
+    /// Code from a newer mpv than this module knows
+    Unknown(c_int),
+    /// A string we were to pass to mpv has a nul byte inside
+    InteriorNul,
+    /// Mpv did what we asked, but returned something we don't understand
+    Convert(ConvertError),
+}
+
+impl ErrorKind {
+    /// `None` for success
+    pub fn from_code(code: c_int) -> Option<Self> {
+        use ErrorKind::*;
+        let kind = match code {
+            sys::mpv_error_MPV_ERROR_SUCCESS => return None,
+            sys::mpv_error_MPV_ERROR_EVENT_QUEUE_FULL => EventQueueFull,
+            sys::mpv_error_MPV_ERROR_NOMEM => NoMem,
+            sys::mpv_error_MPV_ERROR_UNINITIALIZED => Uninitialized,
+            sys::mpv_error_MPV_ERROR_INVALID_PARAMETER => InvalidParameter,
+            sys::mpv_error_MPV_ERROR_OPTION_NOT_FOUND => OptionNotFound,
+            sys::mpv_error_MPV_ERROR_OPTION_FORMAT => OptionFormat,
+            sys::mpv_error_MPV_ERROR_OPTION_ERROR => OptionError,
+            sys::mpv_error_MPV_ERROR_PROPERTY_NOT_FOUND => PropertyNotFound,
+            sys::mpv_error_MPV_ERROR_PROPERTY_FORMAT => PropertyFormat,
+            sys::mpv_error_MPV_ERROR_PROPERTY_UNAVAILABLE => PropertyUnavailable,
+            sys::mpv_error_MPV_ERROR_PROPERTY_ERROR => PropertyError,
+            sys::mpv_error_MPV_ERROR_COMMAND => Command,
+            sys::mpv_error_MPV_ERROR_LOADING_FAILED => LoadingFailed,
+            sys::mpv_error_MPV_ERROR_AO_INIT_FAILED => AoInitFailed,
+            sys::mpv_error_MPV_ERROR_VO_INIT_FAILED => VoInitFailed,
+            sys::mpv_error_MPV_ERROR_NOTHING_TO_PLAY => NothingToPlay,
+            sys::mpv_error_MPV_ERROR_UNKNOWN_FORMAT => UnknownFormat,
+            sys::mpv_error_MPV_ERROR_UNSUPPORTED => Unsupported,
+            sys::mpv_error_MPV_ERROR_NOT_IMPLEMENTED => NotImplemented,
+            sys::mpv_error_MPV_ERROR_GENERIC => Generic,
+            code => Unknown(code),
+        };
+        Some(kind)
+    }
+
+    /// The mpv error code, if this came from mpv
+    pub fn code(self) -> Option<c_int> {
+        use ErrorKind::*;
+        let code = match self {
+            EventQueueFull => sys::mpv_error_MPV_ERROR_EVENT_QUEUE_FULL,
+            NoMem => sys::mpv_error_MPV_ERROR_NOMEM,
+            Uninitialized => sys::mpv_error_MPV_ERROR_UNINITIALIZED,
+            InvalidParameter => sys::mpv_error_MPV_ERROR_INVALID_PARAMETER,
+            OptionNotFound => sys::mpv_error_MPV_ERROR_OPTION_NOT_FOUND,
+            OptionFormat => sys::mpv_error_MPV_ERROR_OPTION_FORMAT,
+            OptionError => sys::mpv_error_MPV_ERROR_OPTION_ERROR,
+            PropertyNotFound => sys::mpv_error_MPV_ERROR_PROPERTY_NOT_FOUND,
+            PropertyFormat => sys::mpv_error_MPV_ERROR_PROPERTY_FORMAT,
+            PropertyUnavailable => sys::mpv_error_MPV_ERROR_PROPERTY_UNAVAILABLE,
+            PropertyError => sys::mpv_error_MPV_ERROR_PROPERTY_ERROR,
+            Command => sys::mpv_error_MPV_ERROR_COMMAND,
+            LoadingFailed => sys::mpv_error_MPV_ERROR_LOADING_FAILED,
+            AoInitFailed => sys::mpv_error_MPV_ERROR_AO_INIT_FAILED,
+            VoInitFailed => sys::mpv_error_MPV_ERROR_VO_INIT_FAILED,
+            NothingToPlay => sys::mpv_error_MPV_ERROR_NOTHING_TO_PLAY,
+            UnknownFormat => sys::mpv_error_MPV_ERROR_UNKNOWN_FORMAT,
+            Unsupported => sys::mpv_error_MPV_ERROR_UNSUPPORTED,
+            NotImplemented => sys::mpv_error_MPV_ERROR_NOT_IMPLEMENTED,
+            Generic => sys::mpv_error_MPV_ERROR_GENERIC,
+            Unknown(code) => code,
+            InteriorNul | Convert(_) => return None,
+        };
+        Some(code)
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::InteriorNul => write!(f, "string contains a nul byte"),
+            ErrorKind::Convert(ConvertError::TypeError) => write!(f, "value has unexpected format"),
+            ErrorKind::Convert(ConvertError::Invalid) => write!(f, "value is invalid"),
+            kind => {
+                let code = kind.code().unwrap_or(sys::mpv_error_MPV_ERROR_GENERIC);
+                // Safety: returns a static string for any code
+                let desc = unsafe { CStr::from_ptr(sys::mpv_error_string(code)) };
+                write!(f, "{}", desc.to_string_lossy())
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    /// Like `get_property`
+    operation: Option<&'static str>,
+    /// Property, option or command name
+    target: Option<String>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            operation: None,
+            target: None,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Which api call failed, if known
+    pub fn operation(&self) -> Option<&'static str> {
+        self.operation
+    }
+
+    /// Property, option or command the call was about, if any
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Attach what we were doing. Keeps the innermost context if there
+    /// already is one.
+    pub(super) fn context(mut self, operation: &'static str, target: Option<&str>) -> Self {
+        if self.operation.is_none() {
+            self.operation = Some(operation);
+            self.target = target.map(str::to_owned);
+        }
+        self
+    }
+
+    pub(super) fn raise(e: c_int) -> Result<(), Self> {
+        Self::raises((), e)
+    }
+
+    pub(super) fn raises<T>(x: T, e: c_int) -> Result<T, Self> {
+        match ErrorKind::from_code(e) {
+            Some(kind) => Err(Error::new(kind)),
+            None => Ok(x),
+        }
+    }
+
+    /// [`raise`](Self::raise) with context
+    pub(super) fn check(e: c_int, operation: &'static str, target: &CStr) -> Result<(), Self> {
+        Self::raise(e).map_err(|err| err.context(operation, Some(&target.to_string_lossy())))
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
+impl From<ConvertError> for Error {
+    fn from(e: ConvertError) -> Self {
+        Error::new(ErrorKind::Convert(e))
+    }
+}
+
+impl From<std::ffi::NulError> for Error {
+    fn from(_: std::ffi::NulError) -> Self {
+        Error::new(ErrorKind::InteriorNul)
+    }
+}
+
+// panic uses Debug for showing error, not Display? Fucking why?
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.operation, &self.target) {
+            (Some(op), Some(target)) => write!(f, "{} {}: {}", op, target, self.kind),
+            (Some(op), None) => write!(f, "{}: {}", op, self.kind),
+            _ => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
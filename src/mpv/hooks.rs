@@ -0,0 +1,94 @@
+//! Hooks stop the player at some point, like before opening a file, until
+//! the handler is done. Like replies, they run whenever events are read, so
+//! if nobody reads events, playback hangs at the first hook.
+//!
+//! See https://mpv.io/manual/master/#hooks for hook names and what can be
+//! done in each one.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+
+use super::{sys, Mpv};
+
+type Handler = Box<dyn FnMut(&Hook<'_>) + Send>;
+
+#[derive(Default)]
+pub(super) struct Hooks {
+    /// By the `reply_userdata` given to `mpv_hook_add`
+    handlers: Mutex<HashMap<u64, Handler>>,
+}
+
+impl Hooks {
+    pub(super) fn insert(&self, id: u64, handler: Handler) {
+        self.handlers.lock().unwrap().insert(id, handler);
+    }
+
+    /// For handlers mpv never accepted
+    pub(super) fn remove(&self, id: u64) {
+        self.handlers.lock().unwrap().remove(&id);
+    }
+
+    /// Returns false if the event is not a hook we handle. The hook is
+    /// continued when the handler returns or panics. A panic is only logged,
+    /// as whoever reads events may hold locks that it would poison.
+    pub(super) fn run(&self, mpv: &Mpv, e: &sys::mpv_event) -> bool {
+        if e.event_id != sys::mpv_event_id_MPV_EVENT_HOOK {
+            return false;
+        }
+        let data = e.data as *const sys::mpv_event_hook;
+        debug_assert!(!data.is_null());
+        // Safety: hook events always have this data
+        let (name, instance) = unsafe { (CStr::from_ptr((*data).name), (*data).id) };
+        let hook = Hook {
+            mpv,
+            name,
+            instance,
+        };
+        // don't hold the lock while running, so handlers can add hooks
+        let Some(mut handler) = self.handlers.lock().unwrap().remove(&e.reply_userdata) else {
+            // continued on drop
+            return true;
+        };
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| handler(&hook)));
+        self.handlers
+            .lock()
+            .unwrap()
+            .insert(e.reply_userdata, handler);
+        drop(hook);
+        if result.is_err() {
+            log::error!("handler of hook {} panicked", name.to_string_lossy());
+        }
+        true
+    }
+}
+
+/// A hook being held. Mpv waits until this is dropped, which happens right
+/// after the handler returns.
+pub struct Hook<'a> {
+    mpv: &'a Mpv,
+    name: &'a CStr,
+    /// Not the id of the handler, but of this particular stop
+    instance: u64,
+}
+
+impl<'a> Hook<'a> {
+    /// For changing properties while mpv waits, like `stream-open-filename`
+    /// to rewrite the url in `on_load`, or `file-local-options/<option>`.
+    /// Synchronous calls are fine here.
+    pub fn mpv(&self) -> &'a Mpv {
+        self.mpv
+    }
+
+    pub fn name(&self) -> &str {
+        // names are the ones we gave to mpv_hook_add
+        self.name.to_str().unwrap_or_default()
+    }
+}
+
+impl<'a> Drop for Hook<'a> {
+    fn drop(&mut self) {
+        unsafe { sys::mpv_hook_continue(self.mpv.ptr, self.instance) };
+    }
+}
@@ -0,0 +1,131 @@
+//! Mpv log messages, and forwarding them to the `log` crate. Mpv doesn't send
+//! any until asked with [`Mpv::request_log_messages`](super::Mpv::request_log_messages).
+//! Tracing users can pick them up with `tracing-log`.
+
+use std::ffi::CStr;
+
+use super::sys;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Disables messages when requested
+    None,
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    /// Verbose
+    V,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub(super) fn from_raw(level: sys::mpv_log_level) -> Self {
+        match level {
+            sys::mpv_log_level_MPV_LOG_LEVEL_NONE => LogLevel::None,
+            sys::mpv_log_level_MPV_LOG_LEVEL_FATAL => LogLevel::Fatal,
+            sys::mpv_log_level_MPV_LOG_LEVEL_ERROR => LogLevel::Error,
+            sys::mpv_log_level_MPV_LOG_LEVEL_WARN => LogLevel::Warn,
+            sys::mpv_log_level_MPV_LOG_LEVEL_INFO => LogLevel::Info,
+            sys::mpv_log_level_MPV_LOG_LEVEL_V => LogLevel::V,
+            sys::mpv_log_level_MPV_LOG_LEVEL_DEBUG => LogLevel::Debug,
+            // anything more verbose than we know
+            _ => LogLevel::Trace,
+        }
+    }
+
+    /// Name for `mpv_request_log_messages`
+    pub(super) fn as_c_str(self) -> &'static CStr {
+        let name: &'static [u8] = match self {
+            LogLevel::None => b"no\0",
+            LogLevel::Fatal => b"fatal\0",
+            LogLevel::Error => b"error\0",
+            LogLevel::Warn => b"warn\0",
+            LogLevel::Info => b"info\0",
+            LogLevel::V => b"v\0",
+            LogLevel::Debug => b"debug\0",
+            LogLevel::Trace => b"trace\0",
+        };
+        // Safety: all of them are nul terminated above
+        unsafe { CStr::from_bytes_with_nul_unchecked(name) }
+    }
+
+    /// `None` for [`LogLevel::None`], which messages never have
+    pub fn to_log(self) -> Option<log::Level> {
+        match self {
+            LogLevel::None => None,
+            LogLevel::Fatal | LogLevel::Error => Some(log::Level::Error),
+            LogLevel::Warn => Some(log::Level::Warn),
+            LogLevel::Info => Some(log::Level::Info),
+            LogLevel::V => Some(log::Level::Debug),
+            LogLevel::Debug | LogLevel::Trace => Some(log::Level::Trace),
+        }
+    }
+}
+
+/// The most verbose level that the `log` filter lets through, so mpv doesn't
+/// produce messages which would be dropped anyway
+impl From<log::LevelFilter> for LogLevel {
+    fn from(filter: log::LevelFilter) -> Self {
+        match filter {
+            log::LevelFilter::Off => LogLevel::None,
+            log::LevelFilter::Error => LogLevel::Error,
+            log::LevelFilter::Warn => LogLevel::Warn,
+            log::LevelFilter::Info => LogLevel::Info,
+            log::LevelFilter::Debug => LogLevel::V,
+            log::LevelFilter::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogMessage {
+    /// Mpv module which sent the message, like `cplayer` or `ffmpeg/demuxer`.
+    /// `overflow` when messages were dropped because nobody read them.
+    pub prefix: String,
+    pub level: LogLevel,
+    /// One line, without the trailing newline
+    pub text: String,
+}
+
+impl LogMessage {
+    /// Safety: `msg` must be valid, as in a log message event
+    pub(super) unsafe fn from_raw(msg: *const sys::mpv_event_log_message) -> Self {
+        debug_assert!(!msg.is_null());
+        let string = |s| CStr::from_ptr(s).to_string_lossy().into_owned();
+        let mut text = string((*msg).text);
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        Self {
+            prefix: string((*msg).prefix),
+            level: LogLevel::from_raw((*msg).log_level),
+            text,
+        }
+    }
+}
+
+/// Pass a message to the `log` logger, with target `mpv::<prefix>`
+pub fn forward(msg: &LogMessage) {
+    let Some(level) = msg.level.to_log() else {
+        return;
+    };
+    if level > log::max_level() {
+        return;
+    }
+    let target = format!("mpv::{}", msg.prefix);
+    let metadata = log::Metadata::builder()
+        .level(level)
+        .target(&target)
+        .build();
+    let logger = log::logger();
+    if logger.enabled(&metadata) {
+        logger.log(
+            &log::Record::builder()
+                .metadata(metadata)
+                .args(format_args!("{}", msg.text))
+                .build(),
+        );
+    }
+}
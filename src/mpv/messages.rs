@@ -0,0 +1,54 @@
+//! Handlers for messages from scripts, sent with `script-message` or
+//! `mp.commandv("script-message", ...)` in lua. By convention the first
+//! argument is the message name, and the rest are its arguments.
+
+use std::collections::HashMap;
+
+use super::event::MpvEvent;
+
+type Handler = Box<dyn FnMut(&[String]) + Send>;
+
+/// Registry of message handlers, by message name. Like
+/// [`Observers`](super::observers::Observers), pass every event through
+/// [`dispatch`](Self::dispatch).
+#[derive(Default)]
+pub struct ClientMessages {
+    handlers: HashMap<String, Handler>,
+}
+
+impl ClientMessages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` with the arguments after the name for every message called
+    /// `name`. Replaces the previous handler of that name.
+    pub fn on<F>(&mut self, name: impl Into<String>, f: F)
+    where
+        F: FnMut(&[String]) + Send + 'static,
+    {
+        self.handlers.insert(name.into(), Box::new(f));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.handlers.remove(name);
+    }
+
+    /// Run the handler if this is a message with a known name, and return
+    /// the event back otherwise
+    pub fn dispatch(&mut self, event: MpvEvent) -> Option<MpvEvent> {
+        match event {
+            MpvEvent::ClientMessage { args } => {
+                let handler = args.first().and_then(|name| self.handlers.get_mut(name));
+                match handler {
+                    Some(handler) => {
+                        handler(&args[1..]);
+                        None
+                    }
+                    None => Some(MpvEvent::ClientMessage { args }),
+                }
+            }
+            event => Some(event),
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! Owned rust version of `mpv_node`, for structured properties and commands.
+//!
+//! See https://mpv.io/manual/master/#property-list for what the nodes of
+//! specific properties look like
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString, OsStr, OsString};
+
+use super::property::Repr;
+use super::{sys, Error, ErrorKind};
+
+/// C representation, for implementing [`ReadProperty`](super::property::ReadProperty)
+/// of node properties
+pub type RawNode = sys::mpv_node;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum MpvNode {
+    #[default]
+    None,
+    /// Bytes as mpv has them. They are UTF-8 for almost everything except
+    /// file names, see [`as_str`](Self::as_str) and
+    /// [`as_os_str`](Self::as_os_str).
+    String(OsString),
+    Flag(bool),
+    Int64(i64),
+    Double(f64),
+    Array(Vec<MpvNode>),
+    /// Mpv doesn't guarantee any order of keys, but we keep whatever order it
+    /// gave us
+    Map(Vec<(String, MpvNode)>),
+    ByteArray(Vec<u8>),
+}
+
+impl MpvNode {
+    /// Copy out a node written by mpv. Doesn't take ownership, so the caller
+    /// still has to free it.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be a valid node, as filled in by mpv
+    pub unsafe fn from_raw(node: *const sys::mpv_node) -> Self {
+        debug_assert!(!node.is_null());
+        let node = &*node;
+        match node.format {
+            sys::mpv_format_MPV_FORMAT_STRING | sys::mpv_format_MPV_FORMAT_OSD_STRING => {
+                let s = CStr::from_ptr(node.u.string);
+                MpvNode::String(os_string_from_bytes(s.to_bytes()))
+            }
+            sys::mpv_format_MPV_FORMAT_FLAG => MpvNode::Flag(node.u.flag != 0),
+            sys::mpv_format_MPV_FORMAT_INT64 => MpvNode::Int64(node.u.int64),
+            sys::mpv_format_MPV_FORMAT_DOUBLE => MpvNode::Double(node.u.double_),
+            sys::mpv_format_MPV_FORMAT_NODE_ARRAY => {
+                let list = &*node.u.list;
+                let values = list_slice(list.values, list.num.max(0) as usize);
+                MpvNode::Array(values.iter().map(|v| MpvNode::from_raw(v)).collect())
+            }
+            sys::mpv_format_MPV_FORMAT_NODE_MAP => {
+                let list = &*node.u.list;
+                let values = list_slice(list.values, list.num.max(0) as usize);
+                let keys = list_slice(list.keys, list.num.max(0) as usize);
+                let entries = keys.iter().zip(values).map(|(k, v)| {
+                    let k = CStr::from_ptr(*k).to_string_lossy().into_owned();
+                    (k, MpvNode::from_raw(v))
+                });
+                MpvNode::Map(entries.collect())
+            }
+            sys::mpv_format_MPV_FORMAT_BYTE_ARRAY => {
+                let ba = &*node.u.ba;
+                let data = list_slice(ba.data as *const u8, ba.size);
+                MpvNode::ByteArray(data.to_vec())
+            }
+            // mpv says not to assume anything about unknown formats
+            _ => MpvNode::None,
+        }
+    }
+
+    /// Copy out data of any format, like in property events, where `data`
+    /// points to a value of `format`
+    ///
+    /// # Safety
+    ///
+    /// `data` must be valid for `format`
+    pub unsafe fn from_data(format: sys::mpv_format, data: *const c_void) -> Self {
+        let mut node = sys::mpv_node {
+            format,
+            ..Default::default()
+        };
+        match format {
+            sys::mpv_format_MPV_FORMAT_NONE => return MpvNode::None,
+            sys::mpv_format_MPV_FORMAT_NODE => return MpvNode::from_raw(data.cast()),
+            sys::mpv_format_MPV_FORMAT_STRING | sys::mpv_format_MPV_FORMAT_OSD_STRING => {
+                node.u.string = *(data as *const *mut c_char)
+            }
+            sys::mpv_format_MPV_FORMAT_FLAG => node.u.flag = *(data as *const c_int),
+            sys::mpv_format_MPV_FORMAT_INT64 => node.u.int64 = *(data as *const i64),
+            sys::mpv_format_MPV_FORMAT_DOUBLE => node.u.double_ = *(data as *const f64),
+            _ => return MpvNode::None,
+        }
+        MpvNode::from_raw(&node)
+    }
+
+    /// Inverse of [`from_data`](Self::from_data): call `f` with a pointer to
+    /// this value in `format`. Returns `None` if this node is not of that
+    /// format. The pointer is only valid during the call.
+    pub fn with_data<R>(
+        &self,
+        format: sys::mpv_format,
+        f: impl FnOnce(*const c_void) -> R,
+    ) -> Option<R> {
+        // strings from mpv never have a nul inside
+        let raw = self.to_raw().ok()?;
+        let fits = match format {
+            sys::mpv_format_MPV_FORMAT_NODE => true,
+            sys::mpv_format_MPV_FORMAT_OSD_STRING => {
+                raw.format == sys::mpv_format_MPV_FORMAT_STRING
+            }
+            _ => raw.format == format,
+        };
+        let result = fits.then(|| {
+            if format == sys::mpv_format_MPV_FORMAT_NODE {
+                f(&raw as *const sys::mpv_node as *const c_void)
+            } else {
+                // all union members start at the beginning, and that's what
+                // a pointer to the data of that format looks like
+                f(&raw.u as *const sys::mpv_node__bindgen_ty_1 as *const c_void)
+            }
+        });
+        // Safety: made by to_raw just above
+        unsafe { MpvNode::free_raw(raw) };
+        result
+    }
+
+    /// Build a c node which points to memory allocated by rust. It must be
+    /// released with [`free_raw`](Self::free_raw), not with
+    /// `mpv_free_node_contents`.
+    ///
+    /// Fails with [`ErrorKind::InteriorNul`] if any string or key has a nul
+    /// byte inside, as mpv would only see the part before it.
+    pub fn to_raw(&self) -> Result<sys::mpv_node, Error> {
+        // checked up front, so that nothing is left half built
+        if self.has_interior_nul() {
+            return Err(ErrorKind::InteriorNul.into());
+        }
+        Ok(self.build_raw())
+    }
+
+    fn has_interior_nul(&self) -> bool {
+        match self {
+            MpvNode::String(s) => os_str_bytes(s).contains(&0),
+            MpvNode::Array(xs) => xs.iter().any(MpvNode::has_interior_nul),
+            MpvNode::Map(xs) => xs
+                .iter()
+                .any(|(k, v)| k.contains('\0') || v.has_interior_nul()),
+            _ => false,
+        }
+    }
+
+    /// [`to_raw`](Self::to_raw) after the check
+    fn build_raw(&self) -> sys::mpv_node {
+        let unchecked = |bytes: &[u8]| {
+            // Safety: to_raw has checked that there are no nul bytes
+            unsafe { CString::from_vec_unchecked(bytes.to_vec()) }.into_raw()
+        };
+        let mut node = sys::mpv_node::default();
+        match self {
+            MpvNode::None => {}
+            MpvNode::String(s) => {
+                node.format = sys::mpv_format_MPV_FORMAT_STRING;
+                node.u.string = unchecked(&os_str_bytes(s));
+            }
+            MpvNode::Flag(x) => {
+                node.format = sys::mpv_format_MPV_FORMAT_FLAG;
+                node.u.flag = c_int::from(*x);
+            }
+            MpvNode::Int64(x) => {
+                node.format = sys::mpv_format_MPV_FORMAT_INT64;
+                node.u.int64 = *x;
+            }
+            MpvNode::Double(x) => {
+                node.format = sys::mpv_format_MPV_FORMAT_DOUBLE;
+                node.u.double_ = *x;
+            }
+            MpvNode::Array(xs) => {
+                let values = xs.iter().map(MpvNode::build_raw).collect::<Box<[_]>>();
+                let list = sys::mpv_node_list {
+                    num: xs.len() as c_int,
+                    values: Box::into_raw(values).cast(),
+                    keys: std::ptr::null_mut(),
+                };
+                node.format = sys::mpv_format_MPV_FORMAT_NODE_ARRAY;
+                node.u.list = Box::into_raw(Box::new(list));
+            }
+            MpvNode::Map(xs) => {
+                let values = xs.iter().map(|(_, v)| v.build_raw()).collect::<Box<[_]>>();
+                let keys = xs
+                    .iter()
+                    .map(|(k, _)| unchecked(k.as_bytes()))
+                    .collect::<Box<[_]>>();
+                let list = sys::mpv_node_list {
+                    num: xs.len() as c_int,
+                    values: Box::into_raw(values).cast(),
+                    keys: Box::into_raw(keys).cast(),
+                };
+                node.format = sys::mpv_format_MPV_FORMAT_NODE_MAP;
+                node.u.list = Box::into_raw(Box::new(list));
+            }
+            MpvNode::ByteArray(xs) => {
+                let data = xs.clone().into_boxed_slice();
+                let ba = sys::mpv_byte_array {
+                    size: data.len(),
+                    data: Box::into_raw(data).cast(),
+                };
+                node.format = sys::mpv_format_MPV_FORMAT_BYTE_ARRAY;
+                node.u.ba = Box::into_raw(Box::new(ba));
+            }
+        }
+        node
+    }
+
+    /// # Safety
+    ///
+    /// `node` must come from [`to_raw`](Self::to_raw) and not be freed
+    /// already
+    pub unsafe fn free_raw(node: sys::mpv_node) {
+        match node.format {
+            sys::mpv_format_MPV_FORMAT_STRING => drop(CString::from_raw(node.u.string)),
+            sys::mpv_format_MPV_FORMAT_NODE_ARRAY | sys::mpv_format_MPV_FORMAT_NODE_MAP => {
+                let list = Box::from_raw(node.u.list);
+                let num = list.num as usize;
+                let values = Box::from_raw(std::ptr::slice_from_raw_parts_mut(list.values, num));
+                for v in values.iter() {
+                    MpvNode::free_raw(*v);
+                }
+                if !list.keys.is_null() {
+                    let keys = Box::from_raw(std::ptr::slice_from_raw_parts_mut(list.keys, num));
+                    for k in keys.iter() {
+                        drop(CString::from_raw(*k));
+                    }
+                }
+            }
+            sys::mpv_format_MPV_FORMAT_BYTE_ARRAY => {
+                let ba = Box::from_raw(node.u.ba);
+                let data = std::ptr::slice_from_raw_parts_mut(ba.data as *mut u8, ba.size);
+                drop(Box::from_raw(data));
+            }
+            _ => {}
+        }
+    }
+
+    /// `None` for strings which are not valid UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MpvNode::String(s) => s.to_str(),
+            _ => None,
+        }
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            MpvNode::Flag(x) => Some(*x),
+            _ => None,
+        }
+    }
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            MpvNode::Int64(x) => Some(*x),
+            _ => None,
+        }
+    }
+    /// Also accepts integers, as mpv is not always consistent about which one
+    /// it returns
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MpvNode::Double(x) => Some(*x),
+            MpvNode::Int64(x) => Some(*x as f64),
+            _ => None,
+        }
+    }
+    /// Any string, like a file name which is not valid UTF-8
+    pub fn as_os_str(&self) -> Option<&OsStr> {
+        match self {
+            MpvNode::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[MpvNode]> {
+        match self {
+            MpvNode::Array(xs) => Some(xs),
+            _ => None,
+        }
+    }
+    pub fn as_map(&self) -> Option<&[(String, MpvNode)]> {
+        match self {
+            MpvNode::Map(xs) => Some(xs),
+            _ => None,
+        }
+    }
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            MpvNode::ByteArray(xs) => Some(xs),
+            _ => None,
+        }
+    }
+
+    /// Lookup a key in a map node
+    pub fn get(&self, key: &str) -> Option<&MpvNode> {
+        self.as_map()?
+            .iter()
+            .find_map(|(k, v)| (k == key).then_some(v))
+    }
+}
+
+impl Default for sys::mpv_node {
+    fn default() -> Self {
+        Self {
+            u: sys::mpv_node__bindgen_ty_1 { int64: 0 },
+            format: sys::mpv_format_MPV_FORMAT_NONE,
+        }
+    }
+}
+
+impl Repr for sys::mpv_node {
+    unsafe fn free_from_mpv(mut self) {
+        sys::mpv_free_node_contents(&mut self);
+    }
+    unsafe fn free_from_rust(self) {
+        MpvNode::free_raw(self)
+    }
+}
+
+impl From<String> for MpvNode {
+    fn from(s: String) -> Self {
+        MpvNode::String(s.into())
+    }
+}
+impl From<&str> for MpvNode {
+    fn from(s: &str) -> Self {
+        MpvNode::String(s.into())
+    }
+}
+impl From<OsString> for MpvNode {
+    fn from(s: OsString) -> Self {
+        MpvNode::String(s)
+    }
+}
+impl From<&OsStr> for MpvNode {
+    fn from(s: &OsStr) -> Self {
+        MpvNode::String(s.to_owned())
+    }
+}
+impl From<bool> for MpvNode {
+    fn from(x: bool) -> Self {
+        MpvNode::Flag(x)
+    }
+}
+impl From<i64> for MpvNode {
+    fn from(x: i64) -> Self {
+        MpvNode::Int64(x)
+    }
+}
+impl From<f64> for MpvNode {
+    fn from(x: f64) -> Self {
+        MpvNode::Double(x)
+    }
+}
+impl From<Vec<MpvNode>> for MpvNode {
+    fn from(xs: Vec<MpvNode>) -> Self {
+        MpvNode::Array(xs)
+    }
+}
+
+/// What mpv expects for file names: the bytes as they are on unix, and UTF-8
+/// elsewhere, which windows paths almost always are
+pub(super) fn os_str_bytes(s: &OsStr) -> std::borrow::Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        std::borrow::Cow::Borrowed(s.as_bytes())
+    }
+    #[cfg(not(unix))]
+    match s.to_string_lossy() {
+        std::borrow::Cow::Borrowed(s) => std::borrow::Cow::Borrowed(s.as_bytes()),
+        std::borrow::Cow::Owned(s) => std::borrow::Cow::Owned(s.into_bytes()),
+    }
+}
+
+/// Inverse of [`os_str_bytes`]
+pub(super) fn os_string_from_bytes(bytes: &[u8]) -> OsString {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        OsStr::from_bytes(bytes).to_owned()
+    }
+    #[cfg(not(unix))]
+    OsString::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// mpv allows null pointers for empty lists, which slices don't
+unsafe fn list_slice<'a, T>(ptr: *const T, num: usize) -> &'a [T] {
+    if ptr.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, num)
+    }
+}
@@ -0,0 +1,89 @@
+//! Callbacks for property changes, instead of matching every event by hand
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::event::MpvEvent;
+use super::node::MpvNode;
+use super::property::{Property, ReadProperty};
+use super::{Mpv, Result, Subscription};
+
+type Callback = Box<dyn FnMut(Property) + Send>;
+
+struct Entry {
+    _subscription: Subscription,
+    callback: Callback,
+}
+
+/// Registry of property observers. It doesn't read events by itself: pass
+/// every event you get through [`dispatch`](Self::dispatch), on whatever
+/// thread you read them. With [`Dispatcher`](crate::dispatch::Dispatcher)
+/// that's the slint event loop thread.
+pub struct Observers {
+    mpv: Arc<Mpv>,
+    entries: HashMap<u64, Entry>,
+}
+
+impl Observers {
+    pub fn new(mpv: Arc<Mpv>) -> Self {
+        Self {
+            mpv,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Observe `P` and call `f` on every change, with `None` when the
+    /// property becomes unavailable, like `duration` with nothing playing.
+    /// Returns an id to pass to [`remove`](Self::remove).
+    pub fn on_change<P, F>(&mut self, mut f: F) -> Result<u64>
+    where
+        P: ReadProperty + 'static,
+        F: FnMut(Option<P>) + Send + 'static,
+    {
+        let subscription = self.mpv.observe_property::<P>()?;
+        let id = subscription.id();
+        let callback = Box::new(move |property: Property| match property {
+            Property::Other {
+                value: MpvNode::None,
+                ..
+            } => f(None),
+            property => {
+                // can only fail if someone else observes a different property
+                // with our id, which mpv doesn't allow
+                if let Some(value) = property.value::<P>() {
+                    f(Some(value))
+                }
+            }
+        });
+        self.entries.insert(
+            id,
+            Entry {
+                _subscription: subscription,
+                callback,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Stop observing. Changes that are already queued are returned by
+    /// [`dispatch`](Self::dispatch), like those of properties nobody
+    /// observes.
+    pub fn remove(&mut self, id: u64) {
+        self.entries.remove(&id);
+    }
+
+    /// Run the callback if this is a change of an observed property, and
+    /// return the event back otherwise
+    pub fn dispatch(&mut self, event: MpvEvent) -> Option<MpvEvent> {
+        match event {
+            MpvEvent::PropertyChange { id, property } => match self.entries.get_mut(&id) {
+                Some(entry) => {
+                    (entry.callback)(property);
+                    None
+                }
+                None => Some(MpvEvent::PropertyChange { id, property }),
+            },
+            event => Some(event),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Futures for async requests. Mpv answers them with reply events carrying the
+//! `reply_userdata` we gave it, so whoever consumes events also resolves these
+//! futures. If nobody reads events, the futures never complete.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::{sys, Mpv, Result};
+
+/// Called with the reply event, which is only valid during the call
+pub(super) type Resolver = Box<dyn FnOnce(&sys::mpv_event) + Send>;
+
+#[derive(Default)]
+pub(super) struct Pending {
+    resolvers: Mutex<HashMap<u64, Resolver>>,
+}
+
+impl Pending {
+    fn insert(&self, id: u64, resolver: Resolver) {
+        self.resolvers.lock().unwrap().insert(id, resolver);
+    }
+
+    fn remove(&self, id: u64) -> Option<Resolver> {
+        self.resolvers.lock().unwrap().remove(&id)
+    }
+
+    /// Returns false if the event is not a reply we're waiting for
+    pub(super) fn resolve(&self, e: &sys::mpv_event) -> bool {
+        let is_reply = e.event_id == sys::mpv_event_id_MPV_EVENT_COMMAND_REPLY
+            || e.event_id == sys::mpv_event_id_MPV_EVENT_GET_PROPERTY_REPLY
+            || e.event_id == sys::mpv_event_id_MPV_EVENT_SET_PROPERTY_REPLY;
+        if !is_reply {
+            return false;
+        }
+        // don't hold the lock while running the resolver
+        let resolver = self.remove(e.reply_userdata);
+        match resolver {
+            Some(resolver) => {
+                resolver(e);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+struct Slot<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Command,
+    Property,
+}
+
+/// Resolves when mpv replies. Dropping it before that forgets the request,
+/// and also aborts it if it's a command.
+pub struct ReplyFuture<'a, T> {
+    mpv: &'a Mpv,
+    id: u64,
+    kind: Kind,
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<'a, T: Send + 'static> ReplyFuture<'a, T> {
+    /// Register a future for request `id`, to be resolved by `convert` when
+    /// the reply arrives. The request must be sent after this.
+    pub(super) fn new<F>(mpv: &'a Mpv, id: u64, kind: Kind, convert: F) -> Self
+    where
+        F: FnOnce(&sys::mpv_event) -> Result<T> + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+        let slot_ = slot.clone();
+        mpv.pending.insert(
+            id,
+            Box::new(move |e| {
+                let result = convert(e);
+                let mut slot = slot_.lock().unwrap();
+                slot.result = Some(result);
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }),
+        );
+        Self {
+            mpv,
+            id,
+            kind,
+            slot,
+        }
+    }
+
+    /// Resolve right away, for when sending the request failed
+    pub(super) fn fail(self, e: super::Error) -> Self {
+        self.mpv.pending.remove(self.id);
+        self.slot.lock().unwrap().result = Some(Err(e));
+        self
+    }
+}
+
+impl<'a, T> ReplyFuture<'a, T> {
+    /// Ask mpv to stop a running command. The future still resolves, most
+    /// likely with an error. Does nothing for property requests.
+    pub fn abort(&self) {
+        if self.kind == Kind::Command {
+            unsafe { sys::mpv_abort_async_command(self.mpv.ptr, self.id) }
+        }
+    }
+}
+
+impl<'a, T> Future for ReplyFuture<'a, T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'a, T> Drop for ReplyFuture<'a, T> {
+    fn drop(&mut self) {
+        if self.mpv.pending.remove(self.id).is_some() {
+            self.abort();
+        }
+    }
+}
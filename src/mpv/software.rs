@@ -0,0 +1,156 @@
+//! Rendering on the CPU, for when there's no GL context to render into, like
+//! with slint's software renderer. Much slower than GL, especially for big
+//! windows.
+
+use std::ffi::{c_int, c_void};
+use std::sync::Arc;
+
+use super::{sys, Mpv, RawRenderContext, Result};
+
+/// Mpv takes slower paths unless both the pointer and the stride are
+/// multiples of this
+const ALIGN: usize = 64;
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Render context for the `sw` api. Frames are rendered into a buffer owned
+/// by the context, which is reused while the size stays the same.
+pub struct SwRenderContext {
+    inner: RawRenderContext,
+    /// Over-allocated by [`ALIGN`], the frame starts at the first aligned byte
+    buffer: Vec<u8>,
+}
+
+impl std::ops::Deref for SwRenderContext {
+    type Target = Mpv;
+
+    fn deref(&self) -> &Mpv {
+        &self.inner.parent
+    }
+}
+
+impl SwRenderContext {
+    pub fn new(parent: Arc<Mpv>) -> Result<Self> {
+        let mut params = [
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_API_TYPE,
+                data: sys::MPV_RENDER_API_TYPE_SW.as_ptr().cast_mut().cast(),
+            },
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_INVALID,
+                data: std::ptr::null_mut(),
+            },
+        ];
+        Ok(Self {
+            inner: RawRenderContext::new(parent, &mut params)?,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn unset_update_callback(&mut self) {
+        self.inner.unset_update_callback()
+    }
+
+    /// Same as [`MpvRenderContext::set_update_callback`](super::MpvRenderContext::set_update_callback)
+    pub fn set_update_callback<F>(&mut self, cb: F)
+    where
+        F: Fn() + Send + 'static,
+    {
+        self.inner.set_update_callback(Box::new(cb))
+    }
+
+    /// Render the current frame, scaled to fit and with black bars if the
+    /// aspect ratio differs
+    pub fn render(&mut self, width: u32, height: u32) -> Result<SwFrame<'_>> {
+        let stride = (width as usize * BYTES_PER_PIXEL).next_multiple_of(ALIGN);
+        let len = stride * height as usize;
+        if len == 0 {
+            // mpv rejects empty surfaces
+            return Ok(SwFrame {
+                width,
+                height,
+                stride,
+                data: &[],
+            });
+        }
+        self.buffer.resize(len + ALIGN, 0);
+        let offset = self.buffer.as_ptr().align_offset(ALIGN);
+        let data = &mut self.buffer[offset..offset + len];
+
+        let mut size: [c_int; 2] = [width as c_int, height as c_int];
+        let mut stride_param: usize = stride;
+        let mut params = [
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_SW_SIZE,
+                data: size.as_mut_ptr().cast(),
+            },
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_SW_FORMAT,
+                // same byte order as RGBA, with garbage for alpha
+                data: c"rgb0".as_ptr().cast_mut().cast(),
+            },
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_SW_STRIDE,
+                data: (&mut stride_param as *mut usize).cast(),
+            },
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_SW_POINTER,
+                data: data.as_mut_ptr() as *mut c_void,
+            },
+            sys::mpv_render_param {
+                type_: sys::mpv_render_param_type_MPV_RENDER_PARAM_INVALID,
+                data: std::ptr::null_mut(),
+            },
+        ];
+        self.inner.render(&mut params)?;
+        Ok(SwFrame {
+            width,
+            height,
+            stride,
+            data,
+        })
+    }
+}
+
+/// A rendered frame, borrowed from the context until the next render
+pub struct SwFrame<'a> {
+    width: u32,
+    height: u32,
+    /// Bytes from one row to the next, more than the row itself
+    stride: usize,
+    data: &'a [u8],
+}
+
+impl<'a> SwFrame<'a> {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Pixels of each row, 4 bytes each: red, green, blue and garbage
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        let row_len = self.width as usize * BYTES_PER_PIXEL;
+        self.data
+            .chunks_exact(self.stride.max(1))
+            .map(move |row| &row[..row_len])
+    }
+
+    /// Copy into tightly packed RGBA, like `SharedPixelBuffer<Rgba8Pixel>`
+    /// uses, making every pixel opaque. `out` must be exactly
+    /// `width * height * 4` bytes.
+    pub fn copy_to_rgba(&self, out: &mut [u8]) {
+        let row_len = self.width as usize * BYTES_PER_PIXEL;
+        assert_eq!(out.len(), row_len * self.height as usize);
+        if row_len == 0 {
+            return;
+        }
+        for (src, dst) in self.rows().zip(out.chunks_exact_mut(row_len)) {
+            dst.copy_from_slice(src);
+            for pixel in dst.chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel[3] = u8::MAX;
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Custom protocols, so mpv can play from anything readable, like
+//! `myapp://archive/video.mkv`. Callbacks run on mpv threads, and block
+//! playback while they do.
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::io::{Read, Seek, SeekFrom};
+use std::panic::AssertUnwindSafe;
+
+use super::sys;
+
+/// Interrupts reads of a stream. Called from another thread than the reads,
+/// and must not block.
+pub type Canceller = Box<dyn Fn() + Send + Sync>;
+
+/// What a [`StreamProvider`] opens. If seeking fails, mpv treats the
+/// stream as not seekable.
+pub trait MpvStream: Read + Seek + Send {
+    /// Total size in bytes, if known
+    fn size(&mut self) -> Option<u64> {
+        None
+    }
+
+    /// Taken once after opening. After it's called, current and future reads
+    /// should return quickly, with an error or end of file.
+    fn canceller(&self) -> Option<Canceller> {
+        None
+    }
+}
+
+impl<T: AsRef<[u8]> + Send> MpvStream for std::io::Cursor<T> {
+    fn size(&mut self) -> Option<u64> {
+        Some(self.get_ref().as_ref().len() as u64)
+    }
+}
+
+impl MpvStream for std::fs::File {
+    fn size(&mut self) -> Option<u64> {
+        self.metadata().ok().map(|m| m.len())
+    }
+}
+
+/// Opens streams for urls of one protocol. Called from mpv threads, possibly
+/// several at once.
+pub trait StreamProvider: Send + Sync + 'static {
+    type Stream: MpvStream + 'static;
+
+    /// `uri` is the whole url, including the `protocol://` prefix. Any error
+    /// makes mpv fail loading the file.
+    fn open(&self, uri: &str) -> std::io::Result<Self::Stream>;
+}
+
+/// Passed to mpv as the cookie. Fields are borrowed separately, as cancel
+/// runs at the same time as reads.
+struct Cookie<S> {
+    stream: S,
+    cancel: Option<Canceller>,
+}
+
+/// Safety: `user_data` must be a `P` which outlives the player
+pub(super) unsafe extern "C" fn open<P: StreamProvider>(
+    user_data: *mut c_void,
+    uri: *mut c_char,
+    info: *mut sys::mpv_stream_cb_info,
+) -> c_int {
+    let provider = &*(user_data as *const P);
+    let uri = CStr::from_ptr(uri).to_string_lossy();
+    let stream = catch(Err(()), || provider.open(&uri).map_err(drop));
+    let Ok(stream) = stream else {
+        return sys::mpv_error_MPV_ERROR_LOADING_FAILED;
+    };
+    let canceller = catch(None, || stream.canceller());
+    let cookie = Box::new(Cookie {
+        stream,
+        cancel: canceller,
+    });
+    let info = &mut *info;
+    info.cookie = Box::into_raw(cookie).cast();
+    info.read_fn = Some(read::<P::Stream>);
+    info.seek_fn = Some(seek::<P::Stream>);
+    info.size_fn = Some(size::<P::Stream>);
+    info.close_fn = Some(close::<P::Stream>);
+    info.cancel_fn = Some(cancel::<P::Stream>);
+    0
+}
+
+unsafe extern "C" fn read<S: MpvStream>(cookie: *mut c_void, buf: *mut c_char, nbytes: u64) -> i64 {
+    let stream = &mut (*(cookie as *mut Cookie<S>)).stream;
+    let buf = std::slice::from_raw_parts_mut(buf as *mut u8, nbytes as usize);
+    catch(-1, || loop {
+        match stream.read(buf) {
+            Ok(n) => return n as i64,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => return -1,
+        }
+    })
+}
+
+unsafe extern "C" fn seek<S: MpvStream>(cookie: *mut c_void, offset: i64) -> i64 {
+    let stream = &mut (*(cookie as *mut Cookie<S>)).stream;
+    let generic = sys::mpv_error_MPV_ERROR_GENERIC as i64;
+    catch(generic, || {
+        match stream.seek(SeekFrom::Start(offset as u64)) {
+            Ok(pos) => pos as i64,
+            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                sys::mpv_error_MPV_ERROR_UNSUPPORTED as i64
+            }
+            Err(_) => generic,
+        }
+    })
+}
+
+unsafe extern "C" fn size<S: MpvStream>(cookie: *mut c_void) -> i64 {
+    let stream = &mut (*(cookie as *mut Cookie<S>)).stream;
+    let unsupported = sys::mpv_error_MPV_ERROR_UNSUPPORTED as i64;
+    catch(unsupported, || {
+        stream.size().map(|s| s as i64).unwrap_or(unsupported)
+    })
+}
+
+unsafe extern "C" fn cancel<S: MpvStream>(cookie: *mut c_void) {
+    let cancel = &(*(cookie as *const Cookie<S>)).cancel;
+    if let Some(cancel) = cancel {
+        catch((), cancel)
+    }
+}
+
+unsafe extern "C" fn close<S: MpvStream>(cookie: *mut c_void) {
+    let cookie = Box::from_raw(cookie as *mut Cookie<S>);
+    catch((), || drop(cookie))
+}
+
+/// Panics can't unwind into mpv
+fn catch<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(fallback)
+}
@@ -0,0 +1,303 @@
+//! Stand-in for libmpv in tests, so they run without it and under Miri or
+//! valgrind. Only the functions the tests reach are implemented here, and
+//! they shadow the real declarations. Anything else still comes from the
+//! bindings, and fails to link if a test reaches it.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_char, c_int, c_void, CStr};
+
+#[path = "sys.rs"]
+mod bindings;
+pub use bindings::*;
+
+type Callback = (unsafe extern "C" fn(*mut c_void), *mut c_void);
+
+struct Handle {
+    wakeup: Option<Callback>,
+}
+
+struct RenderContext {
+    update: Option<Callback>,
+}
+
+thread_local! {
+    static CALLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static REFUSE: Cell<bool> = const { Cell::new(false) };
+}
+
+fn record(call: impl Into<String>) {
+    CALLS.with(|calls| calls.borrow_mut().push(call.into()));
+}
+
+/// Names of stubbed functions called on this thread, and arguments of some
+pub fn take_calls() -> Vec<String> {
+    CALLS.with(|calls| std::mem::take(&mut *calls.borrow_mut()))
+}
+
+/// Make the next call on this thread that mpv may refuse fail with
+/// `MPV_ERROR_NOMEM`
+pub fn refuse_next() {
+    REFUSE.with(|refuse| refuse.set(true));
+}
+
+fn refused() -> bool {
+    REFUSE.with(|refuse| refuse.replace(false))
+}
+
+/// What mpv does when a new frame is ready
+pub unsafe fn trigger_update(ctx: *mut mpv_render_context) {
+    if let Some((cb, data)) = (*(ctx as *mut RenderContext)).update {
+        cb(data)
+    }
+}
+
+fn new_handle() -> *mut mpv_handle {
+    Box::into_raw(Box::new(Handle { wakeup: None })).cast()
+}
+
+pub unsafe extern "C" fn mpv_create() -> *mut mpv_handle {
+    record("create");
+    new_handle()
+}
+
+pub unsafe extern "C" fn mpv_create_client(
+    _ctx: *mut mpv_handle,
+    name: *const c_char,
+) -> *mut mpv_handle {
+    record(format!(
+        "create_client {}",
+        CStr::from_ptr(name).to_string_lossy()
+    ));
+    new_handle()
+}
+
+pub unsafe extern "C" fn mpv_create_weak_client(
+    _ctx: *mut mpv_handle,
+    name: *const c_char,
+) -> *mut mpv_handle {
+    record(format!(
+        "create_weak_client {}",
+        CStr::from_ptr(name).to_string_lossy()
+    ));
+    new_handle()
+}
+
+pub unsafe extern "C" fn mpv_initialize(_ctx: *mut mpv_handle) -> c_int {
+    record("initialize");
+    0
+}
+
+pub unsafe extern "C" fn mpv_destroy(ctx: *mut mpv_handle) {
+    record("destroy");
+    drop(Box::from_raw(ctx as *mut Handle));
+}
+
+pub unsafe extern "C" fn mpv_terminate_destroy(ctx: *mut mpv_handle) {
+    record("terminate_destroy");
+    drop(Box::from_raw(ctx as *mut Handle));
+}
+
+pub unsafe extern "C" fn mpv_set_wakeup_callback(
+    ctx: *mut mpv_handle,
+    cb: Option<unsafe extern "C" fn(d: *mut c_void)>,
+    d: *mut c_void,
+) {
+    (*(ctx as *mut Handle)).wakeup = cb.map(|cb| (cb, d));
+}
+
+pub unsafe extern "C" fn mpv_set_option_string(
+    _ctx: *mut mpv_handle,
+    name: *const c_char,
+    data: *const c_char,
+) -> c_int {
+    let name = CStr::from_ptr(name).to_string_lossy();
+    let data = CStr::from_ptr(data).to_string_lossy();
+    record(format!("set_option_string {}={}", name, data));
+    0
+}
+
+pub unsafe extern "C" fn mpv_set_property(
+    _ctx: *mut mpv_handle,
+    name: *const c_char,
+    _format: mpv_format,
+    _data: *mut c_void,
+) -> c_int {
+    record(format!(
+        "set_property {}",
+        CStr::from_ptr(name).to_string_lossy()
+    ));
+    0
+}
+
+pub unsafe extern "C" fn mpv_observe_property(
+    _mpv: *mut mpv_handle,
+    reply_userdata: u64,
+    name: *const c_char,
+    _format: mpv_format,
+) -> c_int {
+    record(format!(
+        "observe_property {} {}",
+        CStr::from_ptr(name).to_string_lossy(),
+        reply_userdata
+    ));
+    0
+}
+
+pub unsafe extern "C" fn mpv_unobserve_property(
+    _mpv: *mut mpv_handle,
+    registered_reply_userdata: u64,
+) -> c_int {
+    record(format!("unobserve_property {}", registered_reply_userdata));
+    1
+}
+
+pub unsafe extern "C" fn mpv_stream_cb_add_ro(
+    _ctx: *mut mpv_handle,
+    protocol: *const c_char,
+    _user_data: *mut c_void,
+    _open_fn: mpv_stream_cb_open_ro_fn,
+) -> c_int {
+    record(format!(
+        "stream_cb_add_ro {}",
+        CStr::from_ptr(protocol).to_string_lossy()
+    ));
+    0
+}
+
+pub unsafe extern "C" fn mpv_hook_add(
+    _ctx: *mut mpv_handle,
+    reply_userdata: u64,
+    name: *const c_char,
+    _priority: c_int,
+) -> c_int {
+    if refused() {
+        return mpv_error_MPV_ERROR_NOMEM;
+    }
+    record(format!(
+        "hook_add {} {}",
+        CStr::from_ptr(name).to_string_lossy(),
+        reply_userdata
+    ));
+    0
+}
+
+pub unsafe extern "C" fn mpv_hook_continue(_ctx: *mut mpv_handle, id: u64) -> c_int {
+    record(format!("hook_continue {}", id));
+    0
+}
+
+unsafe fn command_name(args: *const mpv_node) -> String {
+    let args = super::node::MpvNode::from_raw(args);
+    let name = match &args {
+        super::node::MpvNode::Array(xs) => xs.first(),
+        node => node.get("name"),
+    };
+    name.and_then(|n| n.as_str()).unwrap_or_default().to_owned()
+}
+
+/// Runs nothing and returns no result
+pub unsafe extern "C" fn mpv_command_node(
+    _ctx: *mut mpv_handle,
+    args: *mut mpv_node,
+    result: *mut mpv_node,
+) -> c_int {
+    record(format!("command {}", command_name(args)));
+    *result = mpv_node::default();
+    0
+}
+
+/// Runs nothing, and never replies: tests make up the reply events
+pub unsafe extern "C" fn mpv_command_node_async(
+    _ctx: *mut mpv_handle,
+    reply_userdata: u64,
+    args: *mut mpv_node,
+) -> c_int {
+    record(format!(
+        "command_async {} {}",
+        command_name(args),
+        reply_userdata
+    ));
+    0
+}
+
+pub unsafe extern "C" fn mpv_abort_async_command(_ctx: *mut mpv_handle, reply_userdata: u64) {
+    record(format!("abort_async_command {}", reply_userdata));
+}
+
+pub unsafe extern "C" fn mpv_free_node_contents(_node: *mut mpv_node) {}
+
+pub unsafe extern "C" fn mpv_render_context_create(
+    res: *mut *mut mpv_render_context,
+    _mpv: *mut mpv_handle,
+    params: *mut mpv_render_param,
+) -> c_int {
+    let mut param = params;
+    while (*param).type_ != mpv_render_param_type_MPV_RENDER_PARAM_INVALID {
+        if (*param).type_ == mpv_render_param_type_MPV_RENDER_PARAM_OPENGL_INIT_PARAMS {
+            // like mpv, only resolve functions during creation
+            let init = &*((*param).data as *const mpv_opengl_init_params);
+            let get_proc_address = init.get_proc_address.unwrap();
+            get_proc_address(init.get_proc_address_ctx, c"glGetString".as_ptr());
+        }
+        param = param.add(1);
+    }
+    record("render_context_create");
+    *res = Box::into_raw(Box::new(RenderContext { update: None })).cast();
+    0
+}
+
+pub unsafe extern "C" fn mpv_render_context_set_update_callback(
+    ctx: *mut mpv_render_context,
+    callback: mpv_render_update_fn,
+    callback_ctx: *mut c_void,
+) {
+    (*(ctx as *mut RenderContext)).update = callback.map(|cb| (cb, callback_ctx));
+    // mpv raises an update right away
+    trigger_update(ctx);
+}
+
+/// Only the sw api: fills the frame with pixels of `1, 2, 3, 0`, and checks
+/// the alignment mpv would like
+pub unsafe extern "C" fn mpv_render_context_render(
+    _ctx: *mut mpv_render_context,
+    params: *mut mpv_render_param,
+) -> c_int {
+    let (mut size, mut stride, mut pointer) = (None, None, None);
+    let mut param = params;
+    while (*param).type_ != mpv_render_param_type_MPV_RENDER_PARAM_INVALID {
+        let data = (*param).data;
+        match (*param).type_ {
+            mpv_render_param_type_MPV_RENDER_PARAM_SW_SIZE => {
+                size = Some(*(data as *const [c_int; 2]))
+            }
+            mpv_render_param_type_MPV_RENDER_PARAM_SW_STRIDE => {
+                stride = Some(*(data as *const usize))
+            }
+            mpv_render_param_type_MPV_RENDER_PARAM_SW_POINTER => pointer = Some(data as *mut u8),
+            _ => {}
+        }
+        param = param.add(1);
+    }
+    let ([w, h], stride, pointer) = (size.unwrap(), stride.unwrap(), pointer.unwrap());
+    assert_eq!(stride % 64, 0);
+    assert_eq!(pointer as usize % 64, 0);
+    record(format!("render_sw {}x{}", w, h));
+    for y in 0..h as usize {
+        let row = std::slice::from_raw_parts_mut(pointer.add(y * stride), w as usize * 4);
+        for pixel in row.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[1, 2, 3, 0]);
+        }
+    }
+    0
+}
+
+pub unsafe extern "C" fn mpv_render_context_free(ctx: *mut mpv_render_context) {
+    record("render_context_free");
+    drop(Box::from_raw(ctx as *mut RenderContext));
+}
+
+pub unsafe extern "C" fn mpv_error_string(_error: c_int) -> *const c_char {
+    c"error from stub".as_ptr()
+}
@@ -0,0 +1,1035 @@
+//! Ownership of callbacks and handles, and conversions between rust and
+//! mpv, checked against the stub in `stub.rs`. Run under Miri or valgrind
+//! to catch leaks and dangling pointers.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::node::MpvNode;
+use super::{sys, ErrorKind, Mpv, MpvRenderContext, SwRenderContext};
+
+fn render_context() -> MpvRenderContext {
+    let mpv = Arc::new(Mpv::new().unwrap());
+    MpvRenderContext::new(mpv, &|_| std::ptr::null()).unwrap()
+}
+
+#[test]
+fn get_proc_addr_is_only_used_during_create() {
+    let mpv = Arc::new(Mpv::new().unwrap());
+    let names = std::cell::RefCell::new(vec![]);
+    let get_proc_addr = |name: &std::ffi::CStr| {
+        names.borrow_mut().push(name.to_owned());
+        std::ptr::null()
+    };
+    let ctx = MpvRenderContext::new(mpv, &get_proc_addr).unwrap();
+    assert_eq!(names.into_inner(), vec![c"glGetString".to_owned()]);
+    drop(ctx);
+}
+
+#[test]
+fn update_callback_runs_until_unset() {
+    let mut ctx = render_context();
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_ = count.clone();
+    ctx.set_update_callback(move || {
+        count_.fetch_add(1, Ordering::Relaxed);
+    });
+    unsafe { sys::trigger_update(ctx.inner.ptr) };
+    assert_eq!(count.load(Ordering::Relaxed), 1);
+    ctx.unset_update_callback();
+    unsafe { sys::trigger_update(ctx.inner.ptr) };
+    assert_eq!(count.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn update_callback_is_freed_when_replaced_and_dropped() {
+    let mut ctx = render_context();
+    let first = Arc::new(());
+    let first_ = first.clone();
+    ctx.set_update_callback(move || {
+        let _ = &first_;
+    });
+    assert_eq!(Arc::strong_count(&first), 2);
+
+    let second = Arc::new(());
+    let second_ = second.clone();
+    ctx.set_update_callback(move || {
+        let _ = &second_;
+    });
+    assert_eq!(Arc::strong_count(&first), 1);
+    assert_eq!(Arc::strong_count(&second), 2);
+
+    drop(ctx);
+    assert_eq!(Arc::strong_count(&second), 1);
+}
+
+#[test]
+fn update_callback_panic_does_not_reach_mpv() {
+    let mut ctx = render_context();
+    ctx.set_update_callback(|| panic!("in update callback"));
+    unsafe { sys::trigger_update(ctx.inner.ptr) };
+    // the slot is still usable afterwards
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_ = count.clone();
+    ctx.set_update_callback(move || {
+        count_.fetch_add(1, Ordering::Relaxed);
+    });
+    unsafe { sys::trigger_update(ctx.inner.ptr) };
+    assert_eq!(count.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn render_context_is_freed_before_the_core() {
+    sys::take_calls();
+    drop(render_context());
+    let calls = sys::take_calls();
+    assert_eq!(
+        calls,
+        [
+            "create",
+            "render_context_create",
+            "render_context_free",
+            "terminate_destroy"
+        ]
+    );
+}
+
+#[test]
+fn interior_nul_is_an_error() {
+    let builder = Mpv::builder().unwrap();
+    let e = builder.option_string("terminal", "n\0o").err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InteriorNul);
+    assert_eq!(e.operation(), Some("set_option"));
+    assert_eq!(e.target(), Some("terminal"));
+
+    let mpv = Mpv::new().unwrap();
+    let e = mpv.create_client("u\0i").err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InteriorNul);
+}
+
+#[test]
+fn interior_nul_never_reaches_mpv() {
+    use super::command::{Command, LoadFile};
+    use super::property::StreamOpenFilename;
+
+    let mpv = Mpv::new().unwrap();
+    sys::take_calls();
+    let e = mpv
+        .command(&Command::LoadFile(LoadFile::new("a\0b")))
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::InteriorNul);
+    assert_eq!(e.operation(), Some("command"));
+    assert_eq!(e.target(), Some("loadfile"));
+    let e = mpv
+        .set_property(&StreamOpenFilename("a\0b".into()))
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::InteriorNul);
+    assert_eq!(e.target(), Some("stream-open-filename"));
+    assert!(sys::take_calls().is_empty());
+
+    mpv.command(&Command::LoadFile(LoadFile::new("ab")))
+        .unwrap();
+    assert_eq!(sys::take_calls(), ["command loadfile"]);
+}
+
+#[test]
+fn clients_only_destroy_themselves() {
+    let mpv = Mpv::new().unwrap();
+    sys::take_calls();
+    drop(mpv.create_client("ui").unwrap());
+    drop(mpv.create_weak_client("logger").unwrap());
+    assert_eq!(
+        sys::take_calls(),
+        [
+            "create_client ui",
+            "destroy",
+            "create_weak_client logger",
+            "destroy"
+        ]
+    );
+    drop(mpv);
+    assert_eq!(sys::take_calls(), ["terminate_destroy"]);
+}
+
+#[test]
+fn subscriptions_unobserve_only_themselves() {
+    use super::property::Pause;
+
+    let mpv = Arc::new(Mpv::new().unwrap());
+    sys::take_calls();
+    let first = mpv.observe_property::<Pause>().unwrap();
+    let second = mpv.observe_property::<Pause>().unwrap();
+    assert_ne!(first.id(), second.id());
+    assert_eq!(
+        sys::take_calls(),
+        [
+            format!("observe_property pause {}", first.id()),
+            format!("observe_property pause {}", second.id()),
+        ]
+    );
+    let id = first.id();
+    drop(first);
+    assert_eq!(sys::take_calls(), [format!("unobserve_property {}", id)]);
+    let id = second.id();
+    drop(second);
+    assert_eq!(sys::take_calls(), [format!("unobserve_property {}", id)]);
+}
+
+#[test]
+fn observers_see_unavailable_properties_and_return_the_rest() {
+    use std::sync::Mutex;
+
+    use super::event::MpvEvent;
+    use super::observers::Observers;
+    use super::property::{MediaTitle, Property};
+
+    let mpv = Arc::new(Mpv::new().unwrap());
+    let mut observers = Observers::new(mpv);
+    let titles = Arc::new(Mutex::new(vec![]));
+    let titles_ = titles.clone();
+    let id = observers
+        .on_change(move |t: Option<MediaTitle>| titles_.lock().unwrap().push(t.map(|t| t.0)))
+        .unwrap();
+
+    let change = |property| MpvEvent::PropertyChange { id, property };
+    let title = Property::MediaTitle(MediaTitle("a.mkv".into()));
+    let unavailable = Property::Other {
+        name: "media-title".into(),
+        value: MpvNode::None,
+    };
+    assert!(observers.dispatch(change(title.clone())).is_none());
+    assert!(observers.dispatch(change(unavailable)).is_none());
+    assert_eq!(*titles.lock().unwrap(), [Some("a.mkv".into()), None]);
+
+    // changes queued before removing come back
+    observers.remove(id);
+    let Some(MpvEvent::PropertyChange { id: returned, .. }) = observers.dispatch(change(title))
+    else {
+        panic!("change not returned");
+    };
+    assert_eq!(returned, id);
+    assert_eq!(titles.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn only_the_player_handle_keeps_stream_providers() {
+    struct Provider;
+    impl super::stream::StreamProvider for Provider {
+        type Stream = std::io::Cursor<Vec<u8>>;
+        fn open(&self, _uri: &str) -> std::io::Result<Self::Stream> {
+            Ok(std::io::Cursor::new(vec![]))
+        }
+    }
+
+    let mpv = Mpv::new().unwrap();
+    let (client, _events) = mpv.create_client("ui").unwrap();
+    sys::take_calls();
+    // the protocol would outlive the client, and the provider with it
+    let e = client.add_stream_protocol("myapp", Provider).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidParameter);
+    assert_eq!(e.target(), Some("myapp"));
+    assert!(sys::take_calls().is_empty());
+
+    mpv.add_stream_protocol("myapp", Provider).unwrap();
+    assert_eq!(sys::take_calls(), ["stream_cb_add_ro myapp"]);
+}
+
+#[test]
+fn sw_frames_are_aligned_and_copied_opaque() {
+    let mut ctx = SwRenderContext::new(Arc::new(Mpv::new().unwrap())).unwrap();
+    sys::take_calls();
+    let frame = ctx.render(3, 2).unwrap();
+    assert_eq!(sys::take_calls(), ["render_sw 3x2"]);
+    assert!(frame.rows().all(|row| row == [1, 2, 3, 0].repeat(3)));
+    let mut rgba = vec![0; 3 * 2 * 4];
+    frame.copy_to_rgba(&mut rgba);
+    assert_eq!(rgba, [1, 2, 3, 255].repeat(6));
+
+    // nothing to render, and mpv isn't asked to
+    assert_eq!(ctx.render(0, 2).unwrap().rows().count(), 0);
+    assert_eq!(sys::take_calls(), [] as [&str; 0]);
+}
+
+#[test]
+fn property_values_decode_by_name_and_format() {
+    use std::ffi::{c_int, CStr};
+
+    use super::property::{format, MediaTitle, Pause, Property, ReadProperty, StrPtr};
+
+    /// Same name as a built-in property, but a type of its own
+    struct Paused(bool);
+    impl ReadProperty for Paused {
+        const NAME: &'static CStr = c"pause";
+        const FORMAT: sys::mpv_format = format::FLAG;
+        type MpvRepr = c_int;
+        fn from_repr(val: c_int) -> Self {
+            Paused(val != 0)
+        }
+    }
+    struct Title(String);
+    impl ReadProperty for Title {
+        const NAME: &'static CStr = c"media-title";
+        const FORMAT: sys::mpv_format = format::STRING;
+        type MpvRepr = StrPtr;
+        fn from_repr(val: StrPtr) -> Self {
+            Title(unsafe { val.as_c_str() }.to_string_lossy().into_owned())
+        }
+    }
+
+    assert!(Property::Pause(Pause(true)).value::<Paused>().unwrap().0);
+    assert!(Property::Pause(Pause(true)).value::<Pause>().unwrap().0);
+    let title = Property::MediaTitle(MediaTitle("clip".into()));
+    assert_eq!(title.value::<Title>().unwrap().0, "clip");
+    let other = Property::Other {
+        name: "pause".into(),
+        value: MpvNode::Flag(false),
+    };
+    assert!(!other.value::<Paused>().unwrap().0);
+
+    // wrong name, and wrong format
+    assert!(Property::Pause(Pause(true)).value::<Title>().is_none());
+    let other = Property::Other {
+        name: "pause".into(),
+        value: MpvNode::String("yes".into()),
+    };
+    assert!(other.value::<Paused>().is_none());
+}
+
+/// Through the c representation and back, freed the way rust allocated it
+fn round_trip(node: &MpvNode) -> MpvNode {
+    let raw = node.to_raw().unwrap();
+    unsafe {
+        let back = MpvNode::from_raw(&raw);
+        MpvNode::free_raw(raw);
+        back
+    }
+}
+
+#[test]
+fn nodes_survive_a_round_trip() {
+    use super::node::os_string_from_bytes;
+
+    let nested = MpvNode::Map(vec![
+        (
+            "list".into(),
+            MpvNode::Array(vec![
+                1i64.into(),
+                MpvNode::Map(vec![("key".into(), "value".into())]),
+                MpvNode::Array(vec![]),
+            ]),
+        ),
+        ("flag".into(), false.into()),
+        ("none".into(), MpvNode::None),
+    ]);
+    let nodes = [
+        MpvNode::None,
+        "".into(),
+        "text".into(),
+        // file names don't have to be UTF-8
+        os_string_from_bytes(b"caf\xe9.mkv").into(),
+        true.into(),
+        i64::MIN.into(),
+        0.5.into(),
+        MpvNode::Array(vec![]),
+        MpvNode::Map(vec![]),
+        MpvNode::ByteArray(vec![]),
+        MpvNode::ByteArray(vec![0, 1, 255]),
+        nested,
+    ];
+    for node in nodes {
+        assert_eq!(round_trip(&node), node);
+    }
+}
+
+#[test]
+fn node_data_is_in_the_requested_format() {
+    use super::property::format;
+
+    let through = |node: &MpvNode, format| {
+        node.with_data(format, |data| unsafe { MpvNode::from_data(format, data) })
+    };
+    for (node, format) in [
+        (MpvNode::from(2.5), format::DOUBLE),
+        (MpvNode::from(7i64), format::INT64),
+        (MpvNode::from(true), format::FLAG),
+        (MpvNode::from("text"), format::STRING),
+        (MpvNode::from("text"), sys::mpv_format_MPV_FORMAT_OSD_STRING),
+        (MpvNode::Array(vec![1i64.into()]), format::NODE),
+        (MpvNode::from(2.5), format::NODE),
+    ] {
+        assert_eq!(through(&node, format), Some(node));
+    }
+    // no conversions between formats
+    assert_eq!(through(&MpvNode::from(7i64), format::DOUBLE), None);
+    assert_eq!(through(&MpvNode::from("1"), format::INT64), None);
+    assert_eq!(through(&MpvNode::Array(vec![]), format::STRING), None);
+}
+
+fn map(entries: &[(&str, MpvNode)]) -> MpvNode {
+    MpvNode::Map(
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect(),
+    )
+}
+
+#[test]
+fn commands_become_named_or_positional_nodes() {
+    use super::command::{Command, LoadFile, LoadFileMode, Seek};
+    use super::property::Volume;
+
+    let cmd = Command::LoadFile(LoadFile::new("a.mkv").mode(LoadFileMode::InsertAt(2)));
+    assert_eq!(
+        cmd.to_node(),
+        map(&[
+            ("name", "loadfile".into()),
+            ("url", "a.mkv".into()),
+            ("flags", "insert-at".into()),
+            ("index", 2i64.into()),
+        ])
+    );
+    assert_eq!(
+        Command::Seek(Seek::relative(-5.0).exact()).to_node(),
+        map(&[
+            ("name", "seek".into()),
+            ("target", (-5.0).into()),
+            ("flags", "relative+exact".into()),
+        ])
+    );
+    assert_eq!(
+        Command::add::<Volume>(2.0).to_node(),
+        MpvNode::Array(vec!["add".into(), "volume".into(), 2.0.into()])
+    );
+    assert_eq!(
+        Command::ScriptMessage(vec!["hello".into(), "world".into()]).to_node(),
+        MpvNode::Array(vec![
+            "script-message".into(),
+            "hello".into(),
+            "world".into()
+        ])
+    );
+    assert_eq!(
+        Command::Raw(vec!["apply-profile".into(), "fast".into()]).to_node(),
+        MpvNode::Array(vec!["apply-profile".into(), "fast".into()])
+    );
+}
+
+#[test]
+fn command_names_match_their_nodes() {
+    use super::command::*;
+
+    let commands = [
+        Command::load_file("a.mkv"),
+        Command::Seek(Seek::absolute(1.0)),
+        Command::Stop {
+            keep_playlist: true,
+        },
+        Command::Quit { code: Some(1) },
+        Command::PlaylistNext { force: false },
+        Command::PlaylistPrev { force: true },
+        Command::PlaylistPlayIndex(0),
+        Command::PlaylistRemove(0),
+        Command::PlaylistClear,
+        Command::PlaylistShuffle,
+        Command::FrameStep,
+        Command::FrameBackStep,
+        Command::SubAdd(SubAdd::new("a.srt").title("English")),
+        Command::SubRemove { id: None },
+        Command::SubReload { id: Some(1) },
+        Command::Screenshot(ScreenshotMode::Video),
+        Command::ScreenshotToFile {
+            filename: "a.png".into(),
+            mode: ScreenshotMode::Window,
+        },
+        Command::Cycle {
+            property: "pause".into(),
+            up: true,
+        },
+        Command::Add {
+            property: "volume".into(),
+            value: 1.0,
+        },
+        Command::Multiply {
+            property: "speed".into(),
+            value: 2.0,
+        },
+        Command::Set {
+            property: "mute".into(),
+            value: "yes".into(),
+        },
+        Command::ShowText {
+            text: "hi".into(),
+            duration_ms: Some(100),
+        },
+        Command::ScriptMessage(vec![]),
+        ExpandPath("~~/".into()).command(),
+        Subprocess::new(["true"]).command(),
+        ScreenshotRaw(ScreenshotMode::Subtitles).command(),
+        Command::Raw(vec!["stop".into()]),
+        Command::Raw(vec![]),
+    ];
+    for cmd in commands {
+        let node = cmd.to_node();
+        let name = match &node {
+            MpvNode::Array(args) => args.first(),
+            node => node.get("name"),
+        };
+        assert_eq!(
+            name.and_then(MpvNode::as_str).unwrap_or_default(),
+            cmd.name()
+        );
+    }
+}
+
+#[test]
+fn load_file_options_are_passed_verbatim() {
+    use super::command::{Command, LoadFile};
+
+    let cmd = Command::LoadFile(
+        LoadFile::new("a.mkv")
+            .option("start", "10")
+            .option("sub-files", "a,b=c.srt")
+            .option("title", "café"),
+    );
+    // lengths are in bytes, and commas and equals signs inside are kept
+    assert_eq!(
+        cmd.to_node().get("options"),
+        Some(&"start=%2%10,sub-files=%9%a,b=c.srt,title=%5%café".into())
+    );
+    let without = Command::load_file("a.mkv").to_node();
+    assert_eq!(without.get("options"), None);
+}
+
+#[test]
+fn command_results_are_decoded() {
+    use super::command::*;
+
+    let result = map(&[("playlist_entry_id", 3i64.into())]);
+    let loaded = LoadFile::decode(result).unwrap();
+    assert_eq!(loaded.playlist_entry_id, Some(3));
+    // older mpv returns nothing
+    assert_eq!(
+        LoadFile::decode(MpvNode::None).unwrap().playlist_entry_id,
+        None
+    );
+
+    assert_eq!(
+        ExpandPath::decode("/home/me/.config/mpv".into()).unwrap(),
+        "/home/me/.config/mpv"
+    );
+    assert!(ExpandPath::decode(MpvNode::None).is_err());
+
+    let result = map(&[
+        ("status", 0i64.into()),
+        ("stdout", MpvNode::ByteArray(b"out\n".to_vec())),
+        ("error_string", "".into()),
+        ("killed_by_us", false.into()),
+    ]);
+    let done = Subprocess::decode(result).unwrap();
+    assert_eq!(done.status, 0);
+    assert_eq!(done.stdout, b"out\n");
+    assert_eq!(done.stderr, b"");
+    assert!(!done.killed_by_us);
+    assert!(Subprocess::decode(map(&[])).is_err());
+
+    let result = map(&[
+        ("w", 2i64.into()),
+        ("h", 1i64.into()),
+        ("stride", 8i64.into()),
+        ("format", "bgr0".into()),
+        ("data", MpvNode::ByteArray(vec![0; 8])),
+    ]);
+    let shot = ScreenshotRaw::decode(result).unwrap();
+    assert_eq!((shot.width, shot.height, shot.stride), (2, 1, 8));
+    assert_eq!(shot.format, "bgr0");
+    assert_eq!(shot.data.len(), 8);
+    assert!(ScreenshotRaw::decode(map(&[("w", 2i64.into())])).is_err());
+}
+
+#[test]
+fn undecodable_results_name_the_command() {
+    use super::command::ExpandPath;
+    use super::property::ConvertError;
+
+    let mpv = Mpv::new().unwrap();
+    // the stub returns no result
+    let e = mpv.command_typed(&ExpandPath("~~/".into())).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::Convert(ConvertError::TypeError));
+    assert_eq!(e.operation(), Some("command"));
+    assert_eq!(e.target(), Some("expand-path"));
+}
+
+/// An event like mpv hands out, pointing to `data`
+fn event(event_id: sys::mpv_event_id, data: *mut std::ffi::c_void) -> sys::mpv_event {
+    sys::mpv_event {
+        event_id,
+        error: 0,
+        reply_userdata: 0,
+        data,
+    }
+}
+
+/// The `reply_userdata` of the only call recorded, which starts with `prefix`
+fn recorded_id(prefix: &str) -> u64 {
+    let calls = sys::take_calls();
+    assert_eq!(calls.len(), 1, "{:?}", calls);
+    calls[0].strip_prefix(prefix).unwrap().parse().unwrap()
+}
+
+#[test]
+fn replies_resolve_their_own_command() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::command::Command;
+
+    let mpv = Mpv::new().unwrap();
+    sys::take_calls();
+    let mut stop = mpv.command_async(&Command::Stop {
+        keep_playlist: false,
+    });
+    let stop_id = recorded_id("command_async stop ");
+    let mut quit = mpv.command_async(&Command::Quit { code: None });
+    let quit_id = recorded_id("command_async quit ");
+    assert_ne!(stop_id, quit_id);
+
+    let mut data = sys::mpv_event_command {
+        result: MpvNode::from("done").to_raw().unwrap(),
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_COMMAND_REPLY,
+        (&mut data as *mut sys::mpv_event_command).cast(),
+    );
+    e.reply_userdata = quit_id;
+    assert!(mpv.pending.resolve(&e));
+    // each reply is only awaited once
+    assert!(!mpv.pending.resolve(&e));
+    unsafe { MpvNode::free_raw(data.result) };
+
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(Pin::new(&mut stop).poll(&mut cx).is_pending());
+    let Poll::Ready(result) = Pin::new(&mut quit).poll(&mut cx) else {
+        panic!("quit is not resolved");
+    };
+    assert_eq!(result.unwrap(), MpvNode::from("done"));
+}
+
+#[test]
+fn replies_to_unknown_requests_are_not_ours() {
+    let mpv = Mpv::new().unwrap();
+    let mut data = sys::mpv_event_command {
+        result: sys::mpv_node::default(),
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_COMMAND_REPLY,
+        (&mut data as *mut sys::mpv_event_command).cast(),
+    );
+    e.reply_userdata = 999;
+    assert!(!mpv.pending.resolve(&e));
+}
+
+#[test]
+fn only_unresolved_commands_abort_on_drop() {
+    use super::command::Command;
+
+    let mpv = Mpv::new().unwrap();
+    sys::take_calls();
+    let cmd = Command::Stop {
+        keep_playlist: false,
+    };
+
+    let pending = mpv.command_async(&cmd);
+    let id = recorded_id("command_async stop ");
+    drop(pending);
+    assert_eq!(sys::take_calls(), [format!("abort_async_command {}", id)]);
+
+    let resolved = mpv.command_async(&cmd);
+    let id = recorded_id("command_async stop ");
+    let mut data = sys::mpv_event_command {
+        result: sys::mpv_node::default(),
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_COMMAND_REPLY,
+        (&mut data as *mut sys::mpv_event_command).cast(),
+    );
+    e.reply_userdata = id;
+    assert!(mpv.pending.resolve(&e));
+    drop(resolved);
+    assert!(sys::take_calls().is_empty());
+}
+
+#[test]
+fn hooks_continue_once_even_if_the_handler_panics() {
+    let mpv = Mpv::new().unwrap();
+    sys::take_calls();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let runs_ = runs.clone();
+    mpv.add_hook("on_load", 0, move |hook| {
+        runs_.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(hook.name(), "on_load");
+        panic!("in hook handler");
+    })
+    .unwrap();
+    let id = recorded_id("hook_add on_load ");
+
+    let mut data = sys::mpv_event_hook {
+        name: c"on_load".as_ptr(),
+        id: 42,
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_HOOK,
+        (&mut data as *mut sys::mpv_event_hook).cast(),
+    );
+    e.reply_userdata = id;
+    for _ in 0..2 {
+        assert!(mpv.hooks.run(&mpv, &e));
+        assert_eq!(sys::take_calls(), ["hook_continue 42"]);
+    }
+    // the handler is kept after panicking
+    assert_eq!(runs.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn refused_hooks_drop_their_handler() {
+    let mpv = Mpv::new().unwrap();
+    let handler = Arc::new(());
+    let handler_ = handler.clone();
+    sys::refuse_next();
+    let e = mpv
+        .add_hook("on_load", 0, move |_| {
+            let _ = &handler_;
+        })
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NoMem);
+    assert_eq!(e.operation(), Some("hook_add"));
+    assert_eq!(e.target(), Some("on_load"));
+    assert_eq!(Arc::strong_count(&handler), 1);
+}
+
+#[test]
+fn log_messages_are_decoded() {
+    use super::event::{convert_event, MpvEvent};
+    use super::logging::LogLevel;
+
+    let mut msg = sys::mpv_event_log_message {
+        prefix: c"ffmpeg/demuxer".as_ptr(),
+        level: c"warn".as_ptr(),
+        text: c"bad packet\n".as_ptr(),
+        log_level: sys::mpv_log_level_MPV_LOG_LEVEL_WARN,
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_LOG_MESSAGE,
+        (&mut msg as *mut sys::mpv_event_log_message).cast(),
+    );
+    let Some(MpvEvent::LogMessage(msg)) = convert_event(&mut e) else {
+        panic!("not a log message");
+    };
+    assert_eq!(msg.prefix, "ffmpeg/demuxer");
+    assert_eq!(msg.level, LogLevel::Warn);
+    // the newline is dropped
+    assert_eq!(msg.text, "bad packet");
+}
+
+#[test]
+fn end_file_reasons_and_errors_are_decoded() {
+    use super::event::{convert_event, EndFileReason, MpvEvent};
+
+    let end_file = |reason, error, playlist_insert_id, playlist_insert_num_entries| {
+        let mut data = sys::mpv_event_end_file {
+            reason,
+            error,
+            playlist_entry_id: 1,
+            playlist_insert_id,
+            playlist_insert_num_entries,
+        };
+        let mut e = event(
+            sys::mpv_event_id_MPV_EVENT_END_FILE,
+            (&mut data as *mut sys::mpv_event_end_file).cast(),
+        );
+        let Some(MpvEvent::EndFile {
+            reason,
+            error,
+            playlist_entry_id,
+            playlist_insert_id,
+            playlist_insert_num_entries,
+        }) = convert_event(&mut e)
+        else {
+            panic!("not an end of file");
+        };
+        assert_eq!(playlist_entry_id, 1);
+        (
+            reason,
+            error,
+            playlist_insert_id,
+            playlist_insert_num_entries,
+        )
+    };
+
+    let eof = end_file(sys::mpv_end_file_reason_MPV_END_FILE_REASON_EOF, 0, 0, 0);
+    assert_eq!(eof, (EndFileReason::Eof, None, 0, 0));
+
+    let error = end_file(
+        sys::mpv_end_file_reason_MPV_END_FILE_REASON_ERROR,
+        sys::mpv_error_MPV_ERROR_LOADING_FAILED,
+        0,
+        0,
+    );
+    assert_eq!(
+        error,
+        (EndFileReason::Error, Some(ErrorKind::LoadingFailed), 0, 0)
+    );
+
+    let redirect = end_file(
+        sys::mpv_end_file_reason_MPV_END_FILE_REASON_REDIRECT,
+        0,
+        5,
+        3,
+    );
+    assert_eq!(redirect, (EndFileReason::Redirect, None, 5, 3));
+
+    let unknown = end_file(100, 0, 0, 0);
+    assert_eq!(unknown, (EndFileReason::Unknown(100), None, 0, 0));
+}
+
+#[test]
+fn log_levels_map_to_log_crate_levels() {
+    use super::logging::LogLevel;
+
+    for (raw, level) in [
+        (sys::mpv_log_level_MPV_LOG_LEVEL_NONE, LogLevel::None),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_FATAL, LogLevel::Fatal),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_ERROR, LogLevel::Error),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_WARN, LogLevel::Warn),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_INFO, LogLevel::Info),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_V, LogLevel::V),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_DEBUG, LogLevel::Debug),
+        (sys::mpv_log_level_MPV_LOG_LEVEL_TRACE, LogLevel::Trace),
+    ] {
+        assert_eq!(LogLevel::from_raw(raw), level);
+    }
+    // levels from a newer mpv are the most verbose
+    assert_eq!(LogLevel::from_raw(100), LogLevel::Trace);
+
+    assert_eq!(LogLevel::None.to_log(), None);
+    assert_eq!(LogLevel::Fatal.to_log(), Some(log::Level::Error));
+    assert_eq!(LogLevel::Warn.to_log(), Some(log::Level::Warn));
+    assert_eq!(LogLevel::V.to_log(), Some(log::Level::Debug));
+    assert_eq!(LogLevel::Debug.to_log(), Some(log::Level::Trace));
+    assert_eq!(LogLevel::None.as_c_str(), c"no");
+    assert_eq!(LogLevel::V.as_c_str(), c"v");
+
+    // only request what the log filter would let through
+    assert_eq!(LogLevel::from(log::LevelFilter::Off), LogLevel::None);
+    assert_eq!(LogLevel::from(log::LevelFilter::Warn), LogLevel::Warn);
+    assert_eq!(LogLevel::from(log::LevelFilter::Debug), LogLevel::V);
+    assert_eq!(LogLevel::from(log::LevelFilter::Trace), LogLevel::Trace);
+}
+
+#[test]
+fn client_messages_are_decoded_and_dispatched() {
+    use std::sync::Mutex;
+
+    use super::event::{convert_event, MpvEvent};
+    use super::messages::ClientMessages;
+
+    let mut args = [c"rename".as_ptr(), c"a.mkv".as_ptr(), c"b.mkv".as_ptr()];
+    let mut msg = sys::mpv_event_client_message {
+        num_args: args.len() as i32,
+        args: args.as_mut_ptr(),
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_CLIENT_MESSAGE,
+        (&mut msg as *mut sys::mpv_event_client_message).cast(),
+    );
+    let event = convert_event(&mut e).unwrap();
+    let MpvEvent::ClientMessage { args } = &event else {
+        panic!("not a client message");
+    };
+    assert_eq!(args, &["rename", "a.mkv", "b.mkv"]);
+
+    let got = Arc::new(Mutex::new(vec![]));
+    let got_ = got.clone();
+    let mut messages = ClientMessages::new();
+    messages.on("rename", move |args| {
+        got_.lock().unwrap().push(args.to_vec())
+    });
+    assert!(messages.dispatch(event).is_none());
+    assert_eq!(*got.lock().unwrap(), [["a.mkv", "b.mkv"]]);
+
+    // messages without a handler, or without a name, come back
+    let unknown = MpvEvent::ClientMessage {
+        args: vec!["other".into()],
+    };
+    assert!(matches!(
+        messages.dispatch(unknown),
+        Some(MpvEvent::ClientMessage { .. })
+    ));
+    let empty = MpvEvent::ClientMessage { args: vec![] };
+    assert!(messages.dispatch(empty).is_some());
+    assert!(matches!(
+        messages.dispatch(MpvEvent::FileLoaded),
+        Some(MpvEvent::FileLoaded)
+    ));
+    messages.remove("rename");
+    let again = MpvEvent::ClientMessage {
+        args: vec!["rename".into()],
+    };
+    assert!(messages.dispatch(again).is_some());
+    assert_eq!(got.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn client_messages_can_be_empty() {
+    use super::event::{convert_event, MpvEvent};
+
+    // mpv may pass null args when there are none
+    let mut msg = sys::mpv_event_client_message {
+        num_args: 0,
+        args: std::ptr::null_mut(),
+    };
+    let mut e = event(
+        sys::mpv_event_id_MPV_EVENT_CLIENT_MESSAGE,
+        (&mut msg as *mut sys::mpv_event_client_message).cast(),
+    );
+    let Some(MpvEvent::ClientMessage { args }) = convert_event(&mut e) else {
+        panic!("not a client message");
+    };
+    assert!(args.is_empty());
+}
+
+/// Bytes after `test://` in the url, or failures for some urls
+struct TestProvider {
+    /// Cloned into every stream, to see when they are freed
+    alive: Arc<()>,
+    cancelled: Arc<AtomicUsize>,
+}
+
+struct TestStream {
+    data: std::io::Cursor<Vec<u8>>,
+    _alive: Arc<()>,
+    cancelled: Arc<AtomicUsize>,
+    panic_on_read: bool,
+}
+
+impl super::stream::StreamProvider for TestProvider {
+    type Stream = TestStream;
+    fn open(&self, uri: &str) -> std::io::Result<TestStream> {
+        match uri {
+            "test://missing" => Err(std::io::ErrorKind::NotFound.into()),
+            "test://panic" => panic!("in open"),
+            _ => Ok(TestStream {
+                data: std::io::Cursor::new(uri.as_bytes()["test://".len()..].to_vec()),
+                _alive: self.alive.clone(),
+                cancelled: self.cancelled.clone(),
+                panic_on_read: uri == "test://panic-on-read",
+            }),
+        }
+    }
+}
+
+impl std::io::Read for TestStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.panic_on_read {
+            panic!("in read");
+        }
+        self.data.read(buf)
+    }
+}
+
+impl std::io::Seek for TestStream {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+impl super::stream::MpvStream for TestStream {
+    fn size(&mut self) -> Option<u64> {
+        self.data.size()
+    }
+    fn canceller(&self) -> Option<super::stream::Canceller> {
+        let cancelled = self.cancelled.clone();
+        Some(Box::new(move || {
+            cancelled.fetch_add(1, Ordering::Relaxed);
+        }))
+    }
+}
+
+impl TestProvider {
+    fn new() -> Self {
+        Self {
+            alive: Arc::new(()),
+            cancelled: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// What mpv does when it opens a url of our protocol
+    fn open(&self, uri: &std::ffi::CStr) -> (std::ffi::c_int, sys::mpv_stream_cb_info) {
+        let mut info = sys::mpv_stream_cb_info {
+            cookie: std::ptr::null_mut(),
+            read_fn: None,
+            seek_fn: None,
+            size_fn: None,
+            close_fn: None,
+            cancel_fn: None,
+        };
+        let user_data = self as *const TestProvider as *mut std::ffi::c_void;
+        let e = unsafe {
+            super::stream::open::<TestProvider>(user_data, uri.as_ptr().cast_mut(), &mut info)
+        };
+        (e, info)
+    }
+}
+
+/// Read up to `len` bytes like mpv does
+unsafe fn read_stream(info: &sys::mpv_stream_cb_info, len: usize) -> (i64, Vec<u8>) {
+    let mut buf = vec![0u8; len];
+    let n = info.read_fn.unwrap()(info.cookie, buf.as_mut_ptr().cast(), len as u64);
+    buf.truncate(n.max(0) as usize);
+    (n, buf)
+}
+
+#[test]
+fn streams_read_seek_and_close() {
+    let provider = TestProvider::new();
+    let (e, info) = provider.open(c"test://hello world");
+    assert_eq!(e, 0);
+    assert_eq!(Arc::strong_count(&provider.alive), 2);
+    unsafe {
+        assert_eq!(info.size_fn.unwrap()(info.cookie), 11);
+        assert_eq!(read_stream(&info, 5), (5, b"hello".to_vec()));
+        assert_eq!(info.seek_fn.unwrap()(info.cookie, 6), 6);
+        assert_eq!(read_stream(&info, 100), (5, b"world".to_vec()));
+        // end of file
+        assert_eq!(read_stream(&info, 100), (0, vec![]));
+        assert_eq!(info.seek_fn.unwrap()(info.cookie, 0), 0);
+        assert_eq!(read_stream(&info, 4), (4, b"hell".to_vec()));
+
+        info.cancel_fn.unwrap()(info.cookie);
+        assert_eq!(provider.cancelled.load(Ordering::Relaxed), 1);
+        info.close_fn.unwrap()(info.cookie);
+    }
+    assert_eq!(Arc::strong_count(&provider.alive), 1);
+}
+
+#[test]
+fn stream_failures_and_panics_stay_out_of_mpv() {
+    let provider = TestProvider::new();
+    for uri in [c"test://missing", c"test://panic"] {
+        let (e, info) = provider.open(uri);
+        assert_eq!(e, sys::mpv_error_MPV_ERROR_LOADING_FAILED);
+        assert!(info.cookie.is_null());
+        assert!(info.close_fn.is_none());
+    }
+
+    let (e, info) = provider.open(c"test://panic-on-read");
+    assert_eq!(e, 0);
+    unsafe {
+        assert_eq!(read_stream(&info, 4).0, -1);
+        info.close_fn.unwrap()(info.cookie);
+    }
+    assert_eq!(Arc::strong_count(&provider.alive), 1);
+}
@@ -0,0 +1,421 @@
+//! Glue between mpv and the `MpvPlayer` global from `ui/mpv-player.slint`.
+//! The global is generated in the crate that compiles the slint files, so
+//! the glue is a macro, [`bind_player!`](crate::bind_player), and the parts
+//! that don't depend on generated types are here.
+
+use std::sync::Arc;
+
+use crate::mpv::command::{Command, Seek};
+use crate::mpv::node::MpvNode;
+use crate::mpv::property::{self, WriteProperty};
+use crate::mpv::reply::ReplyFuture;
+use crate::mpv::{self, Mpv};
+use crate::MpvWidget;
+
+/// An entry of `track-list`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub id: i64,
+    /// `video`, `audio` or `sub`
+    pub kind: String,
+    pub title: String,
+    pub lang: String,
+    pub selected: bool,
+}
+
+/// An entry of `chapter-list`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    /// Seconds from the start
+    pub time: f64,
+}
+
+/// Setters of the global, implemented by [`bind_player!`](crate::bind_player)
+pub trait PlayerState: Clone + Send + 'static {
+    fn set_position(&self, seconds: f64);
+    fn set_duration(&self, seconds: f64);
+    fn set_volume(&self, percent: f64);
+    fn set_muted(&self, muted: bool);
+    fn set_paused(&self, paused: bool);
+    fn set_title(&self, title: String);
+    fn set_chapter(&self, index: i64);
+    fn set_tracks(&self, tracks: Vec<Track>);
+    fn set_chapters(&self, chapters: Vec<Chapter>);
+}
+
+/// Keep `state` in sync with the player. Changes arrive on the slint event
+/// loop, like all events of the widget.
+pub fn observe<S: PlayerState>(widget: &MpvWidget, state: S) -> Result<(), mpv::Error> {
+    // unavailable values, like with nothing loaded, reset the global
+    let s = state.clone();
+    widget.on_change(move |t: Option<property::TimePos>| {
+        s.set_position(t.map_or(0.0, |t| t.0));
+    })?;
+    let s = state.clone();
+    widget.on_change(move |t: Option<property::Duration>| {
+        s.set_duration(t.map_or(0.0, |t| t.0));
+    })?;
+    // options, so these always have a value
+    let s = state.clone();
+    widget.on_change(move |v: Option<property::Volume>| {
+        if let Some(property::Volume(v)) = v {
+            s.set_volume(v)
+        }
+    })?;
+    let s = state.clone();
+    widget.on_change(move |m: Option<property::Mute>| {
+        if let Some(property::Mute(m)) = m {
+            s.set_muted(m)
+        }
+    })?;
+    let s = state.clone();
+    widget.on_change(move |p: Option<property::Pause>| {
+        if let Some(property::Pause(p)) = p {
+            s.set_paused(p)
+        }
+    })?;
+    let s = state.clone();
+    widget.on_change(move |t: Option<property::MediaTitle>| {
+        s.set_title(t.map(|t| t.to_string_lossy().into()).unwrap_or_default());
+    })?;
+    let s = state.clone();
+    widget.on_change(move |c: Option<property::Chapter>| {
+        s.set_chapter(c.map_or(-1, |c| c.0));
+    })?;
+    let s = state.clone();
+    widget.on_change(move |list: Option<property::TrackList>| {
+        s.set_tracks(list.map(|list| tracks(&list.0)).unwrap_or_default());
+    })?;
+    widget.on_change(move |list: Option<property::ChapterList>| {
+        state.set_chapters(list.map(|list| chapters(&list.0)).unwrap_or_default());
+    })?;
+    Ok(())
+}
+
+fn tracks(list: &MpvNode) -> Vec<Track> {
+    let entries = list.as_array().unwrap_or_default();
+    let string = |entry: &MpvNode, key| lossy(entry.get(key));
+    entries
+        .iter()
+        .map(|entry| Track {
+            id: entry
+                .get("id")
+                .and_then(MpvNode::as_i64)
+                .unwrap_or_default(),
+            kind: string(entry, "type"),
+            title: string(entry, "title"),
+            lang: string(entry, "lang"),
+            selected: entry
+                .get("selected")
+                .and_then(MpvNode::as_bool)
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+fn chapters(list: &MpvNode) -> Vec<Chapter> {
+    let entries = list.as_array().unwrap_or_default();
+    entries
+        .iter()
+        .map(|entry| Chapter {
+            title: lossy(entry.get("title")),
+            time: entry
+                .get("time")
+                .and_then(MpvNode::as_f64)
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Titles can come from file names, which aren't always UTF-8
+fn lossy(node: Option<&MpvNode>) -> String {
+    let s = node.and_then(MpvNode::as_os_str).unwrap_or_default();
+    s.to_string_lossy().into_owned()
+}
+
+/// What the callbacks of the global do. Commands are sent without waiting
+/// for them, and failures are logged.
+#[derive(Clone)]
+pub struct Controls {
+    mpv: Arc<Mpv>,
+}
+
+impl Controls {
+    pub fn new(mpv: Arc<Mpv>) -> Self {
+        Self { mpv }
+    }
+
+    pub fn seek(&self, seconds: f64) {
+        self.run(Command::Seek(Seek::absolute(seconds)));
+    }
+
+    pub fn set_volume(&self, percent: f64) {
+        self.set(property::Volume(percent));
+    }
+
+    pub fn toggle_pause(&self) {
+        self.run(Command::cycle::<property::Pause>(true));
+    }
+
+    pub fn toggle_mute(&self) {
+        self.run(Command::cycle::<property::Mute>(true));
+    }
+
+    pub fn load(&self, url: &str) {
+        self.run(Command::load_file(url));
+    }
+
+    /// `kind` is `video`, `audio` or `sub`, and id 0 turns that kind off
+    pub fn select_track(&self, kind: &str, id: i64) {
+        let property = match kind {
+            "video" => "vid",
+            "audio" => "aid",
+            "sub" => "sid",
+            _ => {
+                log::warn!("Unknown track kind {:?}", kind);
+                return;
+            }
+        };
+        let value = if id > 0 { id.to_string() } else { "no".into() };
+        self.run(Command::Set {
+            property: property.into(),
+            value,
+        });
+    }
+
+    pub fn set_chapter(&self, index: i64) {
+        self.set(property::Chapter(index));
+    }
+
+    fn run(&self, cmd: Command) {
+        self.spawn(move |mpv| mpv.command_async(&cmd));
+    }
+
+    /// In the type of the property, so numbers don't go through strings
+    fn set<P: WriteProperty + 'static>(&self, p: P) {
+        self.spawn(move |mpv| mpv.set_property_async(&p));
+    }
+
+    /// Must be called on the slint event loop, which the callbacks of the
+    /// global are
+    fn spawn<T, F>(&self, request: F)
+    where
+        F: for<'a> FnOnce(&'a Mpv) -> ReplyFuture<'a, T> + 'static,
+    {
+        let mpv = self.mpv.clone();
+        let task = async move {
+            match request(&mpv).await {
+                Ok(_) => {}
+                // like pausing when nothing is playing
+                Err(e) if e.kind() == mpv::ErrorKind::PropertyUnavailable => {}
+                Err(e) => log::warn!("{}", e),
+            }
+        };
+        if let Err(e) = slint::spawn_local(task) {
+            log::error!("Can't run mpv command: {}", e);
+        }
+    }
+}
+
+/// Keep the `MpvPlayer` global of a component in sync with a widget, and
+/// wire its callbacks to commands. Use after `slint::include_modules!()`,
+/// with `MpvPlayer`, `MpvTrack` and `MpvChapter` in scope, which they are if
+/// the root file exports them. Returns `Result<(), mpv::Error>`.
+///
+/// ```ignore
+/// mpv_player::bind_player!(App, &app, &widget)?;
+/// ```
+#[macro_export]
+macro_rules! bind_player {
+    ($component:ty, $instance:expr, $widget:expr) => {{
+        #[derive(Clone)]
+        struct State(::slint::Weak<$component>);
+
+        impl State {
+            fn with(&self, f: impl FnOnce(MpvPlayer<'_>)) {
+                if let Some(instance) = self.0.upgrade() {
+                    f(::slint::ComponentHandle::global::<MpvPlayer>(&instance))
+                }
+            }
+        }
+
+        impl $crate::player::PlayerState for State {
+            fn set_position(&self, seconds: f64) {
+                self.with(|g| g.set_position(seconds as f32))
+            }
+            fn set_duration(&self, seconds: f64) {
+                self.with(|g| g.set_duration(seconds as f32))
+            }
+            fn set_volume(&self, percent: f64) {
+                self.with(|g| g.set_volume(percent as f32))
+            }
+            fn set_muted(&self, muted: bool) {
+                self.with(|g| g.set_muted(muted))
+            }
+            fn set_paused(&self, paused: bool) {
+                self.with(|g| g.set_paused(paused))
+            }
+            fn set_title(&self, title: String) {
+                self.with(|g| g.set_title(title.into()))
+            }
+            fn set_chapter(&self, index: i64) {
+                self.with(|g| g.set_chapter(index as i32))
+            }
+            fn set_tracks(&self, tracks: Vec<$crate::player::Track>) {
+                let tracks: Vec<MpvTrack> = tracks
+                    .into_iter()
+                    .map(|t| MpvTrack {
+                        id: t.id as i32,
+                        kind: t.kind.into(),
+                        title: t.title.into(),
+                        lang: t.lang.into(),
+                        selected: t.selected,
+                    })
+                    .collect();
+                let model = ::slint::ModelRc::new(::slint::VecModel::from(tracks));
+                self.with(|g| g.set_tracks(model))
+            }
+            fn set_chapters(&self, chapters: Vec<$crate::player::Chapter>) {
+                let chapters: Vec<MpvChapter> = chapters
+                    .into_iter()
+                    .map(|c| MpvChapter {
+                        title: c.title.into(),
+                        time: c.time as f32,
+                    })
+                    .collect();
+                let model = ::slint::ModelRc::new(::slint::VecModel::from(chapters));
+                self.with(|g| g.set_chapters(model))
+            }
+        }
+
+        let instance: &$component = $instance;
+        let widget: &$crate::MpvWidget = $widget;
+        let controls = $crate::player::Controls::new(widget.mpv().clone());
+        let global = ::slint::ComponentHandle::global::<MpvPlayer>(instance);
+        let c = controls.clone();
+        global.on_seek(move |seconds| c.seek(seconds as f64));
+        let c = controls.clone();
+        global.on_set_volume(move |percent| c.set_volume(percent as f64));
+        let c = controls.clone();
+        global.on_toggle_pause(move || c.toggle_pause());
+        let c = controls.clone();
+        global.on_toggle_mute(move || c.toggle_mute());
+        let c = controls.clone();
+        global.on_load(move |url| c.load(&url));
+        let c = controls.clone();
+        global.on_select_track(move |kind, id| c.select_track(&kind, id as i64));
+        global.on_set_chapter(move |index| controls.set_chapter(index as i64));
+
+        $crate::player::observe(widget, State(::slint::ComponentHandle::as_weak(instance)))
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[(&str, MpvNode)]) -> MpvNode {
+        MpvNode::Map(
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn tracks_are_read_from_track_list() {
+        let list = MpvNode::Array(vec![
+            map(&[
+                ("id", 1i64.into()),
+                ("type", "video".into()),
+                ("selected", true.into()),
+            ]),
+            map(&[
+                ("id", 2i64.into()),
+                ("type", "audio".into()),
+                ("title", "Français".into()),
+                ("lang", "fra".into()),
+                ("selected", false.into()),
+            ]),
+        ]);
+        assert_eq!(
+            tracks(&list),
+            [
+                Track {
+                    id: 1,
+                    kind: "video".into(),
+                    selected: true,
+                    ..Track::default()
+                },
+                Track {
+                    id: 2,
+                    kind: "audio".into(),
+                    title: "Français".into(),
+                    lang: "fra".into(),
+                    selected: false,
+                },
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn titles_that_are_not_utf8_are_replaced() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let title = OsString::from_vec(b"caf\xe9".to_vec());
+        let list = MpvNode::Array(vec![map(&[("title", title.into())])]);
+        assert_eq!(tracks(&list)[0].title, "caf\u{fffd}");
+    }
+
+    #[test]
+    fn track_fields_of_other_types_are_defaults() {
+        let list = MpvNode::Array(vec![
+            map(&[]),
+            map(&[
+                ("id", "1".into()),
+                ("selected", 1i64.into()),
+                ("title", 5i64.into()),
+            ]),
+            MpvNode::Int64(3),
+        ]);
+        assert_eq!(tracks(&list), vec![Track::default(); 3]);
+    }
+
+    #[test]
+    fn chapters_are_read_from_chapter_list() {
+        let list = MpvNode::Array(vec![
+            map(&[("title", "Opening".into()), ("time", 0.0.into())]),
+            map(&[("time", 61.5.into())]),
+            map(&[("title", "End".into()), ("time", 120i64.into())]),
+        ]);
+        assert_eq!(
+            chapters(&list),
+            [
+                Chapter {
+                    title: "Opening".into(),
+                    time: 0.0,
+                },
+                Chapter {
+                    title: "".into(),
+                    time: 61.5,
+                },
+                Chapter {
+                    title: "End".into(),
+                    time: 120.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_that_are_not_arrays_are_empty() {
+        for list in [MpvNode::None, "tracks".into(), map(&[("id", 1i64.into())])] {
+            assert_eq!(tracks(&list), []);
+            assert_eq!(chapters(&list), []);
+        }
+    }
+}
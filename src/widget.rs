@@ -0,0 +1,614 @@
+//! Video from mpv in a slint window. Each widget picks a renderer the first
+//! time the window renders after it's attached, draws frames into an image,
+//! and reads mpv events on the slint event loop.
+
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+use crate::dispatch::Dispatcher;
+use crate::gl;
+use crate::mpv::event::MpvEvent;
+use crate::mpv::observers::Observers;
+use crate::mpv::property::ReadProperty;
+use crate::mpv::{self, Mpv, MpvBuilder, MpvRenderContext, SwRenderContext};
+
+/// Which renderer to use. Forcing one is for debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderBackend {
+    /// GL when slint renders with it, and software otherwise
+    #[default]
+    Auto,
+    Gl,
+    Software,
+}
+
+impl RenderBackend {
+    /// From the `MPV_RENDERER` environment variable: `gl`, `sw` or `auto`
+    pub fn from_env() -> Self {
+        match std::env::var("MPV_RENDERER").as_deref() {
+            Ok("gl") => RenderBackend::Gl,
+            Ok("sw") => RenderBackend::Software,
+            Ok("auto") | Ok("") | Err(_) => RenderBackend::Auto,
+            Ok(other) => {
+                log::warn!("Unknown MPV_RENDERER {:?}, choosing automatically", other);
+                RenderBackend::Auto
+            }
+        }
+    }
+}
+
+/// Why a window or widget couldn't be attached
+#[derive(Debug)]
+pub enum AttachError {
+    /// A window has only one rendering notifier, and someone else has it
+    NotifierTaken,
+    Mpv(mpv::Error),
+}
+
+impl From<mpv::Error> for AttachError {
+    fn from(e: mpv::Error) -> Self {
+        AttachError::Mpv(e)
+    }
+}
+
+impl std::fmt::Display for AttachError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachError::NotifierTaken => write!(f, "window already has a rendering notifier"),
+            AttachError::Mpv(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AttachError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AttachError::NotifierTaken => None,
+            AttachError::Mpv(e) => Some(e),
+        }
+    }
+}
+
+type EventHandler = Box<dyn FnMut(MpvEvent) + Send>;
+
+struct Handlers {
+    observers: Observers,
+    on_event: Option<EventHandler>,
+}
+
+type Players = Rc<RefCell<Vec<Rc<Player>>>>;
+
+/// A window showing any number of players, like a grid of cameras. GL
+/// rendering can only happen in the window's rendering notifier, and there
+/// can only be one of those, so it's shared by all players, and each of them
+/// renders when it has a new frame of its own.
+#[derive(Clone)]
+pub struct MpvWindow {
+    /// None when slint doesn't allow notifiers, and players render on the
+    /// CPU without one
+    players: Option<Players>,
+    redraw: Rc<dyn Fn()>,
+}
+
+impl MpvWindow {
+    /// Take over the rendering notifier of the component's window, with the
+    /// renderer from [`RenderBackend::from_env`]. Fails with
+    /// [`AttachError::NotifierTaken`] if the window already has a notifier.
+    pub fn new<C: slint::ComponentHandle + 'static>(component: &C) -> Result<Self, AttachError> {
+        Self::with_backend(component, RenderBackend::from_env())
+    }
+
+    /// Same as [`new`](Self::new), with the renderer chosen by the caller
+    pub fn with_backend<C: slint::ComponentHandle + 'static>(
+        component: &C,
+        backend: RenderBackend,
+    ) -> Result<Self, AttachError> {
+        let weak = component.as_weak();
+        Self::setup(
+            component.window(),
+            backend,
+            Rc::new(move || {
+                if let Some(component) = weak.upgrade() {
+                    component.window().request_redraw();
+                }
+            }),
+        )
+    }
+
+    /// `redraw` is for players added or removed after the window is shown
+    fn setup(
+        window: &slint::Window,
+        backend: RenderBackend,
+        redraw: Rc<dyn Fn()>,
+    ) -> Result<Self, AttachError> {
+        let players = Players::default();
+        let players_ = players.clone();
+        let r = window.set_rendering_notifier(move |state, graphics_api| match state {
+            slint::RenderingState::BeforeRendering => {
+                // players of dropped widgets are freed here, with the GL
+                // context current
+                players_.borrow_mut().retain(|p| Rc::strong_count(p) > 1);
+                for player in players_.borrow().iter() {
+                    player.before_rendering(backend, graphics_api);
+                }
+            }
+            slint::RenderingState::RenderingTeardown => {
+                players_.borrow_mut().retain(|p| Rc::strong_count(p) > 1);
+                for player in players_.borrow().iter() {
+                    player.teardown();
+                }
+            }
+            _ => {}
+        });
+        let players = match r {
+            Ok(()) => Some(players),
+            Err(slint::SetRenderingNotifierError::Unsupported) => {
+                if backend == RenderBackend::Gl {
+                    log::warn!("GL rendering was forced, but slint doesn't render with GL");
+                }
+                None
+            }
+            Err(_) => return Err(AttachError::NotifierTaken),
+        };
+        // a window that is already shown only runs the new notifier when it
+        // renders again
+        window.request_redraw();
+        Ok(Self { players, redraw })
+    }
+
+    /// Add a player with default options. New frames are passed to
+    /// `image_setter`, at the size `size_getter` returns, both in physical
+    /// pixels.
+    pub fn attach(
+        &self,
+        image_setter: impl Fn(slint::Image) + 'static,
+        size_getter: impl Fn() -> (u32, u32) + 'static,
+    ) -> Result<MpvWidget, mpv::Error> {
+        let builder = Mpv::builder()?.option("terminal", false)?;
+        self.attach_with(builder, image_setter, size_getter)
+    }
+
+    /// Same as [`attach`](Self::attach), with options set by the caller. The
+    /// builder is finished here, and video output is always set to the
+    /// widget.
+    pub fn attach_with(
+        &self,
+        builder: MpvBuilder,
+        image_setter: impl Fn(slint::Image) + 'static,
+        size_getter: impl Fn() -> (u32, u32) + 'static,
+    ) -> Result<MpvWidget, mpv::Error> {
+        // never open a window of mpv's own, even before we render
+        let (mpv, events) = builder.option_string("vo", "libmpv")?.build()?;
+
+        let handlers = Arc::new(Mutex::new(Handlers {
+            observers: Observers::new(mpv.clone()),
+            on_event: None,
+        }));
+        let handlers_ = handlers.clone();
+        let dispatcher = Dispatcher::new(events, move |event| {
+            let mut handlers = handlers_.lock().unwrap();
+            let Some(event) = handlers.observers.dispatch(event) else {
+                return;
+            };
+            if let Some(on_event) = handlers.on_event.as_mut() {
+                on_event(event)
+            }
+        });
+
+        let player = Rc::new(Player {
+            mpv: mpv.clone(),
+            renderer: RefCell::new(None),
+            set_up: Cell::new(false),
+            notify: Arc::new(Notify::default()),
+            image_setter: Box::new(image_setter),
+            size_getter: Box::new(size_getter),
+        });
+        present_frames(Rc::downgrade(&player));
+        match &self.players {
+            Some(players) => {
+                // the renderer is made on the next redraw
+                players.borrow_mut().push(player.clone());
+                (self.redraw)();
+            }
+            None => {
+                *player.renderer.borrow_mut() = Renderer::software(&mpv, &player.notify);
+                player.set_up.set(true);
+            }
+        }
+
+        Ok(MpvWidget {
+            mpv,
+            handlers,
+            _player: player,
+            redraw: self.redraw.clone(),
+            _dispatcher: dispatcher,
+        })
+    }
+}
+
+/// Mpv playing into a slint window. Use [`mpv`](Self::mpv) for commands and
+/// properties, and the `on_` methods to react to what it does.
+///
+/// The renderer is freed on the next redraw of the window after the widget
+/// is dropped, while the GL context is current, or when the window stops
+/// rendering.
+pub struct MpvWidget {
+    mpv: Arc<Mpv>,
+    handlers: Arc<Mutex<Handlers>>,
+    /// The notifier frees players that only it holds
+    _player: Rc<Player>,
+    redraw: Rc<dyn Fn()>,
+    _dispatcher: Dispatcher,
+}
+
+impl Drop for MpvWidget {
+    fn drop(&mut self) {
+        // so that the notifier frees the player soon
+        (self.redraw)();
+    }
+}
+
+impl MpvWidget {
+    /// Attach a single player with default options to the component's
+    /// window. New frames are passed to `image_setter`, at the size
+    /// `size_getter` returns, both in physical pixels.
+    ///
+    /// This takes the window's rendering notifier, and a window has only one,
+    /// so attaching a second widget to the same window fails with
+    /// [`AttachError::NotifierTaken`]. Use [`MpvWindow`] for several players
+    /// in one window.
+    pub fn attach<C: slint::ComponentHandle + 'static>(
+        component: &C,
+        image_setter: impl Fn(slint::Image) + 'static,
+        size_getter: impl Fn() -> (u32, u32) + 'static,
+    ) -> Result<Self, AttachError> {
+        let builder = Mpv::builder()?.option("terminal", false)?;
+        Self::attach_with(
+            builder,
+            RenderBackend::from_env(),
+            component,
+            image_setter,
+            size_getter,
+        )
+    }
+
+    /// Same as [`attach`](Self::attach), with options set by the caller. The
+    /// builder is finished here, and video output is always set to the
+    /// widget.
+    pub fn attach_with<C: slint::ComponentHandle + 'static>(
+        builder: MpvBuilder,
+        backend: RenderBackend,
+        component: &C,
+        image_setter: impl Fn(slint::Image) + 'static,
+        size_getter: impl Fn() -> (u32, u32) + 'static,
+    ) -> Result<Self, AttachError> {
+        let window = MpvWindow::with_backend(component, backend)?;
+        Ok(window.attach_with(builder, image_setter, size_getter)?)
+    }
+
+    pub fn mpv(&self) -> &Arc<Mpv> {
+        &self.mpv
+    }
+
+    /// Called on the slint event loop for every event not taken by property
+    /// observers. Replaces the previous handler. Handlers can't add other
+    /// handlers.
+    pub fn on_event<F>(&self, f: F)
+    where
+        F: FnMut(MpvEvent) + Send + 'static,
+    {
+        self.handlers.lock().unwrap().on_event = Some(Box::new(f));
+    }
+
+    /// See [`Observers::on_change`]. Callbacks run on the slint event loop.
+    pub fn on_change<P, F>(&self, f: F) -> Result<u64, mpv::Error>
+    where
+        P: ReadProperty + 'static,
+        F: FnMut(Option<P>) + Send + 'static,
+    {
+        self.handlers.lock().unwrap().observers.on_change(f)
+    }
+
+    pub fn remove_observer(&self, id: u64) {
+        self.handlers.lock().unwrap().observers.remove(id);
+    }
+}
+
+/// One video of a window, shared by its widget, the rendering notifier and
+/// the task presenting its frames
+struct Player {
+    mpv: Arc<Mpv>,
+    renderer: RefCell<Option<Renderer>>,
+    /// Whether the renderer was made for the current rendering setup, even if
+    /// that failed
+    set_up: Cell<bool>,
+    /// Notified by the update callback of this player's renderer only
+    notify: Arc<Notify>,
+    image_setter: Box<dyn Fn(slint::Image)>,
+    size_getter: Box<dyn Fn() -> (u32, u32)>,
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        // so that presenting frames stops
+        self.notify.notify();
+    }
+}
+
+impl Player {
+    fn before_rendering(&self, backend: RenderBackend, graphics_api: &slint::GraphicsAPI<'_>) {
+        if !self.set_up.replace(true) {
+            let renderer = Renderer::new(backend, &self.mpv, graphics_api, &self.notify);
+            *self.renderer.borrow_mut() = renderer;
+        }
+        if let Some(Renderer::Gl(renderer)) = self.renderer.borrow_mut().as_mut() {
+            let (width, height) = (self.size_getter)();
+            if let Some(image) = renderer.render(width, height) {
+                (self.image_setter)(image);
+            }
+        }
+    }
+
+    fn teardown(&self) {
+        drop(self.renderer.borrow_mut().take());
+        self.set_up.set(false);
+    }
+}
+
+/// Every time mpv has a new frame, show it
+fn present_frames(player: Weak<Player>) {
+    slint::spawn_local(frames(player)).expect("widgets are made after the slint backend");
+}
+
+/// Ends when the player is gone, which notifies one last time on drop
+async fn frames(player: Weak<Player>) {
+    let Some(notify) = player.upgrade().map(|p| p.notify.clone()) else {
+        return;
+    };
+    loop {
+        notify.notified().await;
+        let Some(player) = player.upgrade() else {
+            return;
+        };
+        let image = match player.renderer.borrow_mut().as_mut() {
+            Some(Renderer::Gl(renderer)) => Some(renderer.swap()),
+            Some(Renderer::Sw(renderer)) => {
+                let (width, height) = (player.size_getter)();
+                renderer.render(width, height)
+            }
+            None => None,
+        };
+        if let Some(image) = image {
+            (player.image_setter)(image);
+        }
+    }
+}
+
+enum Renderer {
+    Gl(GlRenderer),
+    Sw(SwRenderer),
+}
+
+impl Renderer {
+    /// Errors are logged, and GL errors fall back to software
+    fn new(
+        backend: RenderBackend,
+        mpv: &Arc<Mpv>,
+        graphics_api: &slint::GraphicsAPI<'_>,
+        notify: &Arc<Notify>,
+    ) -> Option<Self> {
+        match (backend, graphics_api) {
+            (RenderBackend::Software, _) => Self::software(mpv, notify),
+            (_, slint::GraphicsAPI::NativeOpenGL { get_proc_address }) => {
+                let context =
+                    unsafe { glow::Context::from_loader_function_cstr(|s| get_proc_address(s)) };
+                match GlRenderer::new(mpv.clone(), context, get_proc_address) {
+                    Ok(mut renderer) => {
+                        let notify = notify.clone();
+                        renderer.mpv_gl.set_update_callback(move || notify.notify());
+                        Some(Renderer::Gl(renderer))
+                    }
+                    Err(e) => {
+                        log::error!("Can't render with GL, falling back to software: {}", e);
+                        Self::software(mpv, notify)
+                    }
+                }
+            }
+            // like skia on vulkan or metal, which mpv can't render into
+            (backend, _) => {
+                if backend == RenderBackend::Gl {
+                    log::warn!("GL rendering was forced, but slint doesn't render with GL");
+                }
+                Self::software(mpv, notify)
+            }
+        }
+    }
+
+    fn software(mpv: &Arc<Mpv>, notify: &Arc<Notify>) -> Option<Self> {
+        match SwRenderContext::new(mpv.clone()) {
+            Ok(mut mpv_sw) => {
+                let notify = notify.clone();
+                mpv_sw.set_update_callback(move || notify.notify());
+                Some(Renderer::Sw(SwRenderer { mpv_sw }))
+            }
+            Err(e) => {
+                log::error!("Can't render video: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Renders into one of two textures, and shows the other. A new frame swaps
+/// them, which changes the image, which makes slint redraw, which renders
+/// the frame. Rendering only happens with the GL context current, from the
+/// rendering notifier, and only for new frames and sizes, so redraws for
+/// other players in the window don't render this one.
+struct GlRenderer {
+    gl: Rc<glow::Context>,
+    /// The first one is shown and rendered into
+    textures: [gl::Texture; 2],
+    /// Set by a new frame, and cleared once it's rendered
+    pending: bool,
+    mpv_gl: MpvRenderContext,
+}
+
+impl GlRenderer {
+    fn new(
+        mpv: Arc<Mpv>,
+        gl: glow::Context,
+        get_proc_addr: &mpv::CreateContextFn<'_>,
+    ) -> Result<Self, mpv::Error> {
+        let gl = Rc::new(gl);
+        // random size, will be set for real in render
+        let textures = unsafe {
+            [
+                gl::Texture::new(&gl, 320, 200),
+                gl::Texture::new(&gl, 320, 200),
+            ]
+        };
+        let mpv_gl = MpvRenderContext::new(mpv, get_proc_addr)?;
+        Ok(Self {
+            gl,
+            textures,
+            pending: false,
+            mpv_gl,
+        })
+    }
+
+    /// Returns `Some` when the textures were recreated for a new size, and
+    /// None if the shown one has been rendered to or was up to date
+    fn render(&mut self, width: u32, height: u32) -> Option<slint::Image> {
+        let texture = &self.textures[0];
+        let recreated = texture.width != width || texture.height != height;
+        if recreated {
+            self.textures = unsafe {
+                [
+                    gl::Texture::new(&self.gl, width, height),
+                    gl::Texture::new(&self.gl, width, height),
+                ]
+            };
+        } else if !self.pending {
+            return None;
+        }
+        self.pending = false;
+
+        let texture = &self.textures[0];
+        let r = unsafe {
+            texture.with_texture_as_active_fbo(|| {
+                self.mpv_gl
+                    .render(texture.fbo.0.get(), texture.width as _, texture.height as _)
+            })
+        };
+        if let Err(e) = r {
+            log::error!("{}", e);
+        }
+
+        recreated.then(|| self.image())
+    }
+
+    /// Show the other texture, to be rendered into on the next redraw
+    fn swap(&mut self) -> slint::Image {
+        self.textures.swap(0, 1);
+        self.pending = true;
+        self.image()
+    }
+
+    fn image(&self) -> slint::Image {
+        let texture = &self.textures[0];
+        unsafe {
+            slint::BorrowedOpenGLTextureBuilder::new_gl_2d_rgba_texture(
+                texture.texture.0,
+                (texture.width, texture.height).into(),
+            )
+            .build()
+        }
+    }
+}
+
+/// Renders on the CPU, for graphics apis mpv can't render into
+struct SwRenderer {
+    mpv_sw: SwRenderContext,
+}
+
+impl SwRenderer {
+    /// Unlike the GL renderer, every frame is a new image
+    fn render(&mut self, width: u32, height: u32) -> Option<slint::Image> {
+        let frame = match self.mpv_sw.render(width, height) {
+            Ok(frame) => frame,
+            Err(e) => {
+                log::error!("{}", e);
+                return None;
+            }
+        };
+        let mut pixels = slint::SharedPixelBuffer::<slint::Rgba8Pixel>::new(width, height);
+        frame.copy_to_rgba(pixels.make_mut_bytes());
+        Some(slint::Image::from_rgba8(pixels))
+    }
+}
+
+/// Wakes a task from any thread. Notifications while nobody waits are
+/// remembered, and several of them wake only once.
+#[derive(Default)]
+struct Notify {
+    pending: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Notify {
+    fn notify(&self) {
+        self.pending.store(true, Ordering::Release);
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    async fn notified(&self) {
+        std::future::poll_fn(|cx| {
+            let take = || self.pending.swap(false, Ordering::Acquire);
+            if take() {
+                return Poll::Ready(());
+            }
+            *self.waker.lock().unwrap() = Some(cx.waker().clone());
+            // a notification could have come before the waker was stored
+            if take() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::task::{Context, Waker};
+
+    use super::*;
+
+    #[test]
+    fn presenting_frames_ends_with_the_player() {
+        let (mpv, _events) = Mpv::builder().unwrap().build().unwrap();
+        let player = Rc::new(Player {
+            mpv,
+            renderer: RefCell::new(None),
+            set_up: Cell::new(false),
+            notify: Arc::new(Notify::default()),
+            image_setter: Box::new(|_| {}),
+            size_getter: Box::new(|| (0, 0)),
+        });
+        let mut task = std::pin::pin!(frames(Rc::downgrade(&player)));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        // a frame without a renderer, and the task keeps waiting
+        player.notify.notify();
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        drop(player);
+        assert!(task.as_mut().poll(&mut cx).is_ready());
+    }
+}